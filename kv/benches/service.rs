@@ -0,0 +1,123 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use kv::{CommandRequest, MemTable, Service, ServiceInner, SledDb, Storage, Value};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+use tempfile::tempdir;
+
+const TABLE: &str = "bench";
+const KEYS: usize = 1000;
+
+/// 执行 cargo bench --bench service 进行测试
+fn service_benchmark(c: &mut Criterion) {
+    let service: Service = ServiceInner::new(MemTable::new()).into();
+    bench_service(c, "memtable", service);
+
+    let dir = tempdir().unwrap();
+    let service: Service<SledDb> = ServiceInner::new(SledDb::new(&dir)).into();
+    bench_service(c, "sleddb", service);
+}
+
+fn bench_service<Store>(c: &mut Criterion, name: &str, service: Service<Store>)
+where
+    Store: Storage + Send + Sync + 'static,
+{
+    // 不同大小的 value 下单个命令的耗时
+    let mut group = c.benchmark_group(format!("{}/command", name));
+    group.throughput(Throughput::Elements(1));
+    for value_size in [16, 1024, 16 * 1024] {
+        preload(&service, value_size);
+
+        group.bench_with_input(BenchmarkId::new("hget", value_size), &value_size, |b, _| {
+            let mut i = 0;
+            b.iter(|| {
+                i += 1;
+                service.execute(CommandRequest::new_hget(TABLE, key(i % KEYS)))
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("hset", value_size), &value_size, |b, _| {
+            // 构造命令的耗时不计入结果
+            let value: Value = value(value_size).into();
+            let mut i = 0;
+            b.iter_batched(
+                || {
+                    i += 1;
+                    CommandRequest::new_hset(TABLE, key(i % KEYS), value.clone())
+                },
+                |cmd| service.execute(cmd),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+
+    // hgetall 的耗时和 table 的大小成正比，单独统计每秒读出的 key 数
+    let mut group = c.benchmark_group(format!("{}/hgetall", name));
+    group.throughput(Throughput::Elements(KEYS as u64));
+    group.sample_size(20);
+    group.bench_function("hgetall", |b| {
+        b.iter(|| service.execute(CommandRequest::new_hgetall(TABLE)))
+    });
+    group.finish();
+
+    // 多个线程同时执行 8:2 的 hget/hset，统计总的吞吐量
+    let mut group = c.benchmark_group(format!("{}/concurrent", name));
+    group.throughput(Throughput::Elements(1));
+    preload(&service, 128);
+    for threads in [1, 4, 8] {
+        group.bench_with_input(BenchmarkId::new("mix", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| run_concurrent(&service, threads, iters))
+        });
+    }
+    group.finish();
+}
+
+fn run_concurrent<Store>(service: &Service<Store>, threads: usize, iters: u64) -> Duration
+where
+    Store: Storage + Send + Sync + 'static,
+{
+    let per_thread = (iters as usize + threads - 1) / threads;
+    let start = Instant::now();
+    let handles: Vec<_> = (0..threads)
+        .map(|t| {
+            let service = service.clone();
+            thread::spawn(move || {
+                let value: Value = value(128).into();
+                for i in 0..per_thread {
+                    let key = key((t * per_thread + i) % KEYS);
+                    let cmd = match i % 10 {
+                        0 | 1 => CommandRequest::new_hset(TABLE, key, value.clone()),
+                        _ => CommandRequest::new_hget(TABLE, key),
+                    };
+                    service.execute(cmd);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    start.elapsed()
+}
+
+fn preload<Store: Storage>(service: &Service<Store>, value_size: usize) {
+    for i in 0..KEYS {
+        service.execute(CommandRequest::new_hset(
+            TABLE,
+            key(i),
+            value(value_size).into(),
+        ));
+    }
+}
+
+fn key(i: usize) -> String {
+    format!("key-{:08}", i)
+}
+
+fn value(size: usize) -> String {
+    "v".repeat(size)
+}
+
+criterion_group!(benches, service_benchmark);
+criterion_main!(benches);
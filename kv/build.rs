@@ -1,7 +1,12 @@
 fn main() {
     let mut config = prost_build::Config::new();
     config.bytes(&["."]);
-    config.type_attribute(".", "#[derive(PartialOrd)]");
+    config.type_attribute(".", "#[derive(serde::Serialize, serde::Deserialize)]");
+    config.type_attribute(".", "#[serde(rename_all = \"snake_case\")]");
+    // prost 生成的 enum 已经 derive 了 PartialOrd，这里只给需要排序的 message 加上
+    config.type_attribute(".abi.Kvpair", "#[derive(PartialOrd)]");
+    config.type_attribute(".abi.Value", "#[derive(PartialOrd)]");
+    config.type_attribute(".abi.Encoded", "#[derive(PartialOrd)]");
     config
         .out_dir("src/pb")
         .compile_protos(&["abi.proto"], &["."])
@@ -25,5 +25,14 @@ async fn main() -> Result<()> {
         info!("Got response {:?}", data);
     }
 
+    // 读取不存在的 key，出错时把响应还原成 KvError
+    client.send(CommandRequest::new_hget("table1", "nonexist")).await?;
+    if let Some(Ok(data)) = client.next().await {
+        match data.into_result() {
+            Ok(res) => info!("Got response {:?}", res),
+            Err(e) => info!("Got error {:?}", e),
+        }
+    }
+
     Ok(())
 }
@@ -0,0 +1,112 @@
+use anyhow::Result;
+use axum::{
+    extract::{ConnectInfo, Extension, Path},
+    handler::{get, post},
+    http::StatusCode,
+    AddExtensionLayer, Json, Router,
+};
+use kv::{
+    CommandRequest, CommandResponse, LimitConfig, MemTable, Quota, RateLimiter, Service,
+    ServiceInner, Value,
+};
+use std::net::SocketAddr;
+use tracing::info;
+
+/// 运行命令启动 HTTP/JSON 网关 RUST_LOG=info cargo run --example http_server --quiet
+/// curl -X PUT -d '{"value":{"string":"world"}}' -H 'content-type: application/json' \
+///     http://127.0.0.1:9528/tables/t1/keys/hello
+/// curl http://127.0.0.1:9528/tables/t1/keys/hello
+/// curl -d '{"request_data":{"hgetall":{"table":"t1"}}}' -H 'content-type: application/json' \
+///     http://127.0.0.1:9528/command
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let service: Service = ServiceInner::new(MemTable::new()).into();
+    // 和 server 使用一样的配额
+    let limiter = RateLimiter::new(LimitConfig {
+        per_ip: Some(Quota::new(100.0, 200)),
+        max_in_flight: Some(16),
+        max_value_size: Some(1024 * 1024),
+    });
+
+    let app = Router::new()
+        .route("/tables/:table", get(hgetall))
+        .route("/tables/:table/keys/:key", get(hget).put(hset).delete(hdel))
+        .route("/command", post(command))
+        .layer(AddExtensionLayer::new(service))
+        .layer(AddExtensionLayer::new(limiter));
+
+    let addr = "127.0.0.1:9528".parse()?;
+    info!("Start listening on {}", addr);
+    axum::Server::bind(&addr)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr, _>())
+        .await?;
+
+    Ok(())
+}
+
+async fn hget(
+    Path((table, key)): Path<(String, String)>,
+    Extension(svc): Extension<Service>,
+    Extension(limiter): Extension<RateLimiter>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> (StatusCode, Json<CommandResponse>) {
+    execute(&svc, &limiter, addr, CommandRequest::new_hget(table, key))
+}
+
+async fn hgetall(
+    Path(table): Path<String>,
+    Extension(svc): Extension<Service>,
+    Extension(limiter): Extension<RateLimiter>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> (StatusCode, Json<CommandResponse>) {
+    execute(&svc, &limiter, addr, CommandRequest::new_hgetall(table))
+}
+
+async fn hset(
+    Path((table, key)): Path<(String, String)>,
+    Extension(svc): Extension<Service>,
+    Extension(limiter): Extension<RateLimiter>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(value): Json<Value>,
+) -> (StatusCode, Json<CommandResponse>) {
+    execute(
+        &svc,
+        &limiter,
+        addr,
+        CommandRequest::new_hset(table, key, value),
+    )
+}
+
+async fn hdel(
+    Path((table, key)): Path<(String, String)>,
+    Extension(svc): Extension<Service>,
+    Extension(limiter): Extension<RateLimiter>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+) -> (StatusCode, Json<CommandResponse>) {
+    execute(&svc, &limiter, addr, CommandRequest::new_hdel(table, key))
+}
+
+async fn command(
+    Extension(svc): Extension<Service>,
+    Extension(limiter): Extension<RateLimiter>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(cmd): Json<CommandRequest>,
+) -> (StatusCode, Json<CommandResponse>) {
+    execute(&svc, &limiter, addr, cmd)
+}
+
+// 检查配额后执行命令，并把 CommandResponse 中的 status 作为 HTTP 状态码
+fn execute(
+    svc: &Service,
+    limiter: &RateLimiter,
+    addr: SocketAddr,
+    cmd: CommandRequest,
+) -> (StatusCode, Json<CommandResponse>) {
+    let res = match limiter.connection(addr.ip()).check(&cmd) {
+        Ok(_guard) => svc.execute(cmd),
+        Err(e) => e.into(),
+    };
+    let status = StatusCode::from_u16(res.status as _).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (status, Json(res))
+}
@@ -0,0 +1,64 @@
+use anyhow::Result;
+use bytes::BytesMut;
+use kv::{
+    LimitConfig, MemTable, Quota, RateLimiter, RespDecoder, RespFrame, RespService, Service,
+    ServiceInner,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+use tracing::info;
+
+/// 运行命令启动兼容 Redis 协议的 kv 服务 RUST_LOG=info cargo run --example resp_server --quiet
+/// 然后使用 redis-cli -p 6380 hset t1 hello world 进行测试
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let service: Service = ServiceInner::new(MemTable::new()).into();
+    let resp = RespService::new(service);
+    // 和 server 使用一样的配额
+    let limiter = RateLimiter::new(LimitConfig {
+        per_ip: Some(Quota::new(100.0, 200)),
+        max_in_flight: Some(16),
+        max_value_size: Some(1024 * 1024),
+    });
+    let addr = "127.0.0.1:6380";
+    let listener = TcpListener::bind(addr).await?;
+    info!("Start listening on {}", addr);
+    loop {
+        let (mut stream, addr) = listener.accept().await?;
+        info!("Client {:?} connected", addr);
+        let svc = resp.clone();
+        let limiter = limiter.connection(addr.ip());
+        tokio::spawn(async move {
+            let mut buf = BytesMut::with_capacity(4096);
+            let mut out = BytesMut::with_capacity(4096);
+            let mut decoder = RespDecoder::default();
+            'conn: loop {
+                match stream.read_buf(&mut buf).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {}
+                }
+                // 一次读取可能包含多个命令（pipeline），依次处理后一起返回
+                loop {
+                    match decoder.decode(&mut buf) {
+                        Ok(Some(frame)) => svc.execute_limited(frame, &limiter).encode(&mut out),
+                        Ok(None) => break,
+                        Err(e) => {
+                            // 协议错误无法恢复，返回错误后断开连接
+                            RespFrame::from(e).encode(&mut out);
+                            let _ = stream.write_all(&out).await;
+                            break 'conn;
+                        }
+                    }
+                }
+                if stream.write_all(&out).await.is_err() {
+                    break;
+                }
+                out.clear();
+            }
+            info!("Client {:?} disconnected", addr);
+        });
+    }
+}
@@ -1,7 +1,10 @@
 use anyhow::Result;
 use async_prost::AsyncProstStream;
 use futures::prelude::*;
-use kv::{CommandRequest, CommandResponse, MemTable, Service, ServiceInner};
+use kv::{
+    command_request::RequestData, CommandRequest, CommandResponse, KvError, LimitConfig, MemTable,
+    Quota, RateLimiter, Service, ServiceInner,
+};
 use tokio::net::TcpListener;
 use tracing::info;
 
@@ -10,6 +13,12 @@ use tracing::info;
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
     let service: Service = ServiceInner::new(MemTable::new()).into();
+    // 每个 IP 每秒 100 个请求，允许 200 个突发请求，单个 value 不超过 1MB
+    let limiter = RateLimiter::new(LimitConfig {
+        per_ip: Some(Quota::new(100.0, 200)),
+        max_in_flight: Some(16),
+        max_value_size: Some(1024 * 1024),
+    });
     let addr = "127.0.0.1:9527";
     let listener = TcpListener::bind(addr).await?;
     info!("Start listening on {}", addr);
@@ -17,12 +26,74 @@ async fn main() -> Result<()> {
         let (stream, addr) = listener.accept().await?;
         info!("Client {:?} connected", addr);
         let svc = service.clone();
+        let limiter = limiter.connection(addr.ip());
         tokio::spawn(async move {
             let mut stream =
                 AsyncProstStream::<_, CommandRequest, CommandResponse, _>::from(stream).for_async();
-            while let Some(Ok(cmd)) = stream.next().await {
-                let res = svc.execute(cmd);
-                stream.send(res).await.unwrap();
+            // 订阅期间收到的请求，结束订阅之后处理
+            let mut pending = None;
+            'conn: loop {
+                let cmd = match pending.take() {
+                    Some(cmd) => cmd,
+                    None => match stream.next().await {
+                        Some(Ok(cmd)) => cmd,
+                        _ => break,
+                    },
+                };
+
+                // Watch 命令把连接变成事件流，回放 from_seq 之后的事件并持续推送新的修改事件
+                if let Some(RequestData::Watch(ref watch)) = cmd.request_data {
+                    // Watch 也要检查配额，但订阅期间不算作处理中的请求
+                    let sub = limiter.check(&cmd).and_then(|_| svc.watch(watch.clone()));
+                    let mut sub = match sub {
+                        Ok(sub) => sub,
+                        Err(e) => {
+                            if stream.send(e.into()).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+                    loop {
+                        tokio::select! {
+                            event = sub.recv() => match event {
+                                Some(event) => {
+                                    if stream.send(vec![event].into()).await.is_err() {
+                                        break 'conn;
+                                    }
+                                }
+                                // 消费太慢被断开，通知客户端重新 Watch，连接继续处理其它请求
+                                None => {
+                                    let e = KvError::QuotaExceeded(
+                                        "subscriber is lagging, watch again from the last seq + 1"
+                                            .into(),
+                                    );
+                                    if stream.send(e.into()).await.is_err() {
+                                        break 'conn;
+                                    }
+                                    break;
+                                }
+                            },
+                            // 客户端发来新的请求时结束订阅（drop sub 取消订阅），然后处理这个请求
+                            req = stream.next() => match req {
+                                Some(Ok(cmd)) => {
+                                    pending = Some(cmd);
+                                    break;
+                                }
+                                _ => break 'conn,
+                            },
+                        }
+                    }
+                    continue;
+                }
+
+                let res = match limiter.check(&cmd) {
+                    Ok(_guard) => svc.execute(cmd),
+                    Err(e) => e.into(),
+                };
+                if stream.send(res).await.is_err() {
+                    break;
+                }
             }
             info!("Client {:?} disconnected", addr);
         });
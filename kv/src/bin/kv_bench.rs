@@ -0,0 +1,295 @@
+use anyhow::{anyhow, Result};
+use async_prost::AsyncProstStream;
+use clap::{builder::RangedU64ValueParser, Parser};
+use futures::prelude::*;
+use kv::{CommandRequest, CommandResponse, MemTable, Service, ServiceInner, SledDb, Storage};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::info;
+
+const TABLE: &str = "bench";
+
+/// 压测 kv server，不指定 --addr 时在进程内启动一个 server
+///
+/// 压测工具的依赖只在打开 kv-bench feature 时引入，需要用 --release 运行：
+/// cargo run --release --features kv-bench --bin kv-bench -- -c 16 -p 8 -n 100000 --mix 8:2:0
+/// 压测已经运行的 server（比如 cargo run --release --example server）：
+/// cargo run --release --features kv-bench --bin kv-bench -- --addr 127.0.0.1:9527 --mix 5:5:0
+/// 查看所有参数：cargo run --release --features kv-bench --bin kv-bench -- --help
+#[derive(Parser, Debug)]
+struct Opts {
+    /// 压测已经运行的 server
+    #[clap(long)]
+    addr: Option<String>,
+    /// 进程内的 server 使用 sled 存储
+    #[clap(long)]
+    sled: bool,
+    /// 并发的连接数
+    #[clap(short, long, default_value = "16", value_parser = positive())]
+    concurrency: usize,
+    /// 每个连接上一次发送的请求数
+    #[clap(short, long, default_value = "1", value_parser = positive())]
+    pipeline: usize,
+    /// 总的请求数
+    #[clap(short = 'n', long, default_value = "100000")]
+    requests: usize,
+    /// hget:hset:hgetall 的比例
+    #[clap(long, default_value = "8:2:0")]
+    mix: Mix,
+    /// table 中 key 的数量
+    #[clap(long, default_value = "1000", value_parser = positive())]
+    keys: usize,
+    /// key 的字节数
+    #[clap(long, default_value = "16")]
+    key_size: usize,
+    /// value 的字节数
+    #[clap(long, default_value = "128")]
+    value_size: usize,
+}
+
+// 连接数、pipeline 和 key 的数量都不能为 0
+fn positive() -> RangedU64ValueParser<usize> {
+    RangedU64ValueParser::new().range(1..)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Mix {
+    hget: u32,
+    hset: u32,
+    hgetall: u32,
+}
+
+impl FromStr for Mix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts = s
+            .split(':')
+            .map(|v| v.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()?;
+        // 比例之和不能溢出，生成请求时直接在 [0, 总和) 中取随机数
+        let total = parts.iter().try_fold(0u32, |sum, v| sum.checked_add(*v));
+        match (&parts[..], total) {
+            (&[hget, hset, hgetall], Some(total)) if total > 0 => Ok(Self {
+                hget,
+                hset,
+                hgetall,
+            }),
+            _ => Err(anyhow!("Mix should be hget:hset:hgetall, got {}", s)),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let opts = Opts::parse();
+
+    // sled 的目录要保留到压测结束，之后自动删除
+    let mut sled_dir = None;
+    let addr = match opts.addr {
+        Some(ref addr) => addr.clone(),
+        None if opts.sled => {
+            let dir = sled_dir.insert(tempfile::tempdir()?);
+            let service: Service<SledDb> = ServiceInner::new(SledDb::new(dir.path())).into();
+            start_server(service).await?
+        }
+        None => start_server(ServiceInner::new(MemTable::new()).into()).await?,
+    };
+    info!("Benchmarking {} with {:?}", addr, opts);
+
+    preload(&addr, &opts).await?;
+
+    let start = Instant::now();
+    let workers: Vec<_> = (0..opts.concurrency)
+        .map(|id| {
+            let addr = addr.clone();
+            let requests =
+                opts.requests / opts.concurrency + (id < opts.requests % opts.concurrency) as usize;
+            let workload = Workload::new(&opts, id as u64);
+            tokio::spawn(async move { run_worker(&addr, workload, requests).await })
+        })
+        .collect();
+
+    let mut latencies = Vec::with_capacity(opts.requests);
+    let mut errors = 0;
+    for worker in workers {
+        let report = worker.await??;
+        latencies.extend(report.latencies);
+        errors += report.errors;
+    }
+    let elapsed = start.elapsed();
+
+    print_report(&mut latencies, errors, elapsed);
+    Ok(())
+}
+
+// 在进程内启动 server，返回监听的地址
+async fn start_server<Store>(service: Service<Store>) -> Result<String>
+where
+    Store: Storage + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    info!("Start in-process server on {}", addr);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let svc = service.clone();
+            tokio::spawn(async move {
+                let mut stream =
+                    AsyncProstStream::<_, CommandRequest, CommandResponse, _>::from(stream)
+                        .for_async();
+                while let Some(Ok(cmd)) = stream.next().await {
+                    let res = svc.execute(cmd);
+                    if stream.send(res).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    Ok(addr.to_string())
+}
+
+// 写入所有的 key，保证 hget 都能命中
+async fn preload(addr: &str, opts: &Opts) -> Result<()> {
+    let workload = Workload::new(opts, 0);
+    let mut client = connect(addr).await?;
+    for keys in (0..opts.keys).collect::<Vec<_>>().chunks(100) {
+        for &i in keys {
+            client.feed(workload.hset(i)).await?;
+        }
+        client.flush().await?;
+        for _ in keys {
+            client
+                .next()
+                .await
+                .ok_or_else(|| anyhow!("Server closed"))??;
+        }
+    }
+    info!("Preloaded {} keys", opts.keys);
+    Ok(())
+}
+
+struct Workload {
+    rng: StdRng,
+    mix: Mix,
+    keys: usize,
+    key_size: usize,
+    value: String,
+    pipeline: usize,
+}
+
+impl Workload {
+    fn new(opts: &Opts, seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            mix: opts.mix,
+            keys: opts.keys,
+            key_size: opts.key_size,
+            value: "v".repeat(opts.value_size),
+            pipeline: opts.pipeline,
+        }
+    }
+
+    fn next(&mut self) -> CommandRequest {
+        let Mix {
+            hget,
+            hset,
+            hgetall,
+        } = self.mix;
+        let n = self.rng.gen_range(0..hget + hset + hgetall);
+        let i = self.rng.gen_range(0..self.keys);
+        if n < hget {
+            CommandRequest::new_hget(TABLE, self.key(i))
+        } else if n < hget + hset {
+            self.hset(i)
+        } else {
+            CommandRequest::new_hgetall(TABLE)
+        }
+    }
+
+    fn hset(&self, i: usize) -> CommandRequest {
+        CommandRequest::new_hset(TABLE, self.key(i), self.value.clone().into())
+    }
+
+    fn key(&self, i: usize) -> String {
+        format!("{:0width$}", i, width = self.key_size)
+    }
+}
+
+struct Report {
+    latencies: Vec<Duration>,
+    errors: usize,
+}
+
+async fn run_worker(addr: &str, mut workload: Workload, requests: usize) -> Result<Report> {
+    let mut client = connect(addr).await?;
+    let mut report = Report {
+        latencies: Vec::with_capacity(requests),
+        errors: 0,
+    };
+
+    let mut sent = 0;
+    while sent < requests {
+        // 一次发送 pipeline 个请求，再依次读取响应
+        let batch = workload.pipeline.min(requests - sent);
+        let mut started = Vec::with_capacity(batch);
+        for _ in 0..batch {
+            started.push(Instant::now());
+            client.feed(workload.next()).await?;
+        }
+        client.flush().await?;
+
+        for start in started {
+            let res = client
+                .next()
+                .await
+                .ok_or_else(|| anyhow!("Server closed"))??;
+            report.latencies.push(start.elapsed());
+            if res.status != 200 {
+                report.errors += 1;
+            }
+        }
+        sent += batch;
+    }
+
+    Ok(report)
+}
+
+async fn connect(
+    addr: &str,
+) -> Result<
+    impl Stream<Item = Result<CommandResponse, std::io::Error>>
+        + Sink<CommandRequest, Error = std::io::Error>
+        + Unpin,
+> {
+    let stream = TcpStream::connect(addr).await?;
+    Ok(AsyncProstStream::<_, CommandResponse, CommandRequest, _>::from(stream).for_async())
+}
+
+fn print_report(latencies: &mut [Duration], errors: usize, elapsed: Duration) {
+    latencies.sort_unstable();
+    let percentile = |p: f64| {
+        let idx = ((latencies.len() as f64 * p) as usize).min(latencies.len().saturating_sub(1));
+        latencies.get(idx).copied().unwrap_or_default()
+    };
+
+    println!("requests:   {}", latencies.len());
+    println!("errors:     {}", errors);
+    println!("elapsed:    {:.2?}", elapsed);
+    println!(
+        "throughput: {:.0} req/s",
+        latencies.len() as f64 / elapsed.as_secs_f64()
+    );
+    println!("p50:        {:.2?}", percentile(0.5));
+    println!("p99:        {:.2?}", percentile(0.99));
+    println!(
+        "max:        {:.2?}",
+        latencies.last().copied().unwrap_or_default()
+    );
+}
@@ -0,0 +1,235 @@
+use crate::{value, Codec, Encoded, KvError, Storage, Value};
+use prost::Message;
+use serde::{de::DeserializeOwned, Serialize};
+
+/// 把 Rust 数据结构编码成 Value 的编码器
+pub trait ValueCodec<T> {
+    /// 编码格式，会和编码后的数据一起存储
+    const CODEC: Codec;
+
+    fn encode(value: &T) -> Result<Vec<u8>, KvError>;
+    fn decode(data: &[u8]) -> Result<T, KvError>;
+}
+
+/// 使用 serde_json 编码
+pub struct JsonCodec;
+/// 使用 bincode 编码
+pub struct BincodeCodec;
+/// 使用 prost 编码，适用于 protobuf 生成的数据结构
+pub struct ProtobufCodec;
+
+impl JsonCodec {
+    // 编码只需要 T: Serialize
+    fn to_vec<T: Serialize>(value: &T) -> Result<Vec<u8>, KvError> {
+        serde_json::to_vec(value)
+            .map_err(|e| KvError::CodecError("encode".into(), Codec::Json, e.to_string()))
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> ValueCodec<T> for JsonCodec {
+    const CODEC: Codec = Codec::Json;
+
+    fn encode(value: &T) -> Result<Vec<u8>, KvError> {
+        Self::to_vec(value)
+    }
+
+    fn decode(data: &[u8]) -> Result<T, KvError> {
+        serde_json::from_slice(data)
+            .map_err(|e| KvError::CodecError("decode".into(), Codec::Json, e.to_string()))
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> ValueCodec<T> for BincodeCodec {
+    const CODEC: Codec = Codec::Bincode;
+
+    fn encode(value: &T) -> Result<Vec<u8>, KvError> {
+        bincode::serialize(value)
+            .map_err(|e| KvError::CodecError("encode".into(), Codec::Bincode, e.to_string()))
+    }
+
+    fn decode(data: &[u8]) -> Result<T, KvError> {
+        bincode::deserialize(data)
+            .map_err(|e| KvError::CodecError("decode".into(), Codec::Bincode, e.to_string()))
+    }
+}
+
+impl<T: Message + Default> ValueCodec<T> for ProtobufCodec {
+    const CODEC: Codec = Codec::Protobuf;
+
+    fn encode(value: &T) -> Result<Vec<u8>, KvError> {
+        Ok(value.encode_to_vec())
+    }
+
+    fn decode(data: &[u8]) -> Result<T, KvError> {
+        Ok(T::decode(data)?)
+    }
+}
+
+impl Value {
+    /// 使用编码器 C 把 data 编码成 Value，编码格式会记录在 Value 中
+    pub fn encode_with<C: ValueCodec<T>, T>(data: &T) -> Result<Self, KvError> {
+        Ok(Self::encoded(C::CODEC, C::encode(data)?))
+    }
+
+    fn encoded(codec: Codec, data: Vec<u8>) -> Self {
+        Self {
+            value: Some(value::Value::Encoded(Encoded {
+                codec: codec as _,
+                data: data.into(),
+            })),
+        }
+    }
+
+    /// 使用编码器 C 解码 Value，记录的编码格式和 C 不一致时返回错误
+    pub fn decode_with<C: ValueCodec<T>, T>(&self) -> Result<T, KvError> {
+        match self.value {
+            Some(value::Value::Encoded(ref v)) if v.codec == C::CODEC as i32 => C::decode(&v.data),
+            Some(value::Value::Encoded(ref v)) => Err(KvError::CodecError(
+                "decode".into(),
+                C::CODEC,
+                format!("value is encoded with {:?}", v.codec()),
+            )),
+            _ => Err(KvError::ConvertError(self.clone(), "Encoded".into())),
+        }
+    }
+
+    /// 根据 Value 中记录的编码格式解码，只支持 serde 相关的编码格式
+    pub fn decode_as<T: Serialize + DeserializeOwned>(&self) -> Result<T, KvError> {
+        match self.value {
+            Some(value::Value::Encoded(ref v)) => match v.codec() {
+                Codec::Json => JsonCodec::decode(&v.data),
+                Codec::Bincode => BincodeCodec::decode(&v.data),
+                codec => Err(KvError::CodecError(
+                    "decode".into(),
+                    codec,
+                    "codec is not supported by serde".into(),
+                )),
+            },
+            _ => Err(KvError::ConvertError(self.clone(), "Encoded".into())),
+        }
+    }
+}
+
+/// 在 Storage 之上提供存取任意 Rust 数据结构的辅助函数
+pub trait TypedStorage: Storage {
+    /// 使用编码器 C 编码 value 并存入 table，返回之前的值
+    fn set_with<C: ValueCodec<T>, T>(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: &T,
+    ) -> Result<Option<Value>, KvError> {
+        self.set(table, key, Value::encode_with::<C, T>(value)?)
+    }
+
+    /// 从 table 中获取 key 并使用编码器 C 解码
+    fn get_with<C: ValueCodec<T>, T>(&self, table: &str, key: &str) -> Result<Option<T>, KvError> {
+        self.get(table, key)?
+            .map(|v| v.decode_with::<C, T>())
+            .transpose()
+    }
+
+    /// 使用 JSON 编码 value 并存入 table
+    fn set_json<T: Serialize>(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: &T,
+    ) -> Result<Option<Value>, KvError> {
+        let value = Value::encoded(Codec::Json, JsonCodec::to_vec(value)?);
+        self.set(table, key, value)
+    }
+
+    /// 从 table 中获取 key，并根据存储时记录的编码格式解码
+    fn get_as<T: Serialize + DeserializeOwned>(
+        &self,
+        table: &str,
+        key: &str,
+    ) -> Result<Option<T>, KvError> {
+        self.get(table, key)?.map(|v| v.decode_as()).transpose()
+    }
+}
+
+impl<S: Storage> TypedStorage for S {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Kvpair, MemTable, SledDb};
+    use serde::Deserialize;
+    use tempfile::tempdir;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+        age: u8,
+        tags: Vec<String>,
+    }
+
+    fn user() -> User {
+        User {
+            name: "tyr".into(),
+            age: 18,
+            tags: vec!["rust".into(), "kv".into()],
+        }
+    }
+
+    #[test]
+    fn memtable_typed_interface_should_work() {
+        let store = MemTable::new();
+        test_typed_interface(store);
+    }
+
+    #[test]
+    fn sleddb_typed_interface_should_work() {
+        let dir = tempdir().unwrap();
+        let store = SledDb::new(dir);
+        test_typed_interface(store);
+    }
+
+    #[test]
+    fn decode_with_wrong_codec_should_fail() {
+        let v = Value::encode_with::<JsonCodec, _>(&user()).unwrap();
+        let res = v.decode_with::<BincodeCodec, User>();
+        assert!(matches!(
+            res,
+            Err(KvError::CodecError(op, Codec::Bincode, _)) if op == "decode"
+        ));
+
+        let v: Value = "hello".into();
+        assert!(v.decode_as::<User>().is_err());
+    }
+
+    fn test_typed_interface(store: impl Storage) {
+        // JSON 编码存取
+        assert_eq!(store.set_json("t1", "json", &user()), Ok(None));
+        assert_eq!(store.get_as("t1", "json"), Ok(Some(user())));
+        // 只实现了 Serialize 的类型也可以存入
+        store.set_json("t1", "str", &"hello").unwrap();
+        assert_eq!(store.get_as("t1", "str"), Ok(Some("hello".to_string())));
+
+        // bincode 编码存取，get_as 根据记录的编码格式解码
+        store
+            .set_with::<BincodeCodec, _>("t1", "bin", &user())
+            .unwrap();
+        assert_eq!(store.get_as("t1", "bin"), Ok(Some(user())));
+        assert_eq!(
+            store.get_with::<BincodeCodec, _>("t1", "bin"),
+            Ok(Some(user()))
+        );
+
+        // protobuf 编码存取，get_as 无法处理
+        let pair = Kvpair::new("hello", "world".into());
+        store
+            .set_with::<ProtobufCodec, _>("t1", "pb", &pair)
+            .unwrap();
+        assert_eq!(
+            store.get_with::<ProtobufCodec, _>("t1", "pb"),
+            Ok(Some(pair))
+        );
+        assert!(store.get_as::<User>("t1", "pb").is_err());
+
+        // 不存在的 key 返回 None
+        assert_eq!(store.get_as::<User>("t1", "none"), Ok(None));
+    }
+}
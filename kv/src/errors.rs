@@ -1,4 +1,4 @@
-use crate::Value;
+use crate::{Codec, Value};
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -9,9 +9,27 @@ pub enum KvError {
     #[error("Command is invalid: `{0}`")]
     InvalidCommand(String),
     #[error("Cannot convert value {:0} to {1}")]
-    ConvertError(Value, &'static str),
+    ConvertError(Value, String),
     #[error("Cannot process command {0} with table: {1}, key: {2}. Error: {}")]
-    StorageError(&'static str, String, String, String),
+    StorageError(String, String, String, String),
+
+    #[error("Cannot {0} value with codec {1:?}: {2}")]
+    CodecError(String, Codec, String),
+
+    #[error("Cannot {0} file {1}. Error: {2}")]
+    BackupError(String, String, String),
+
+    #[error("Events from seq {0} are no longer available, the oldest is {1}")]
+    EventsExpired(u64, u64),
+
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+
+    #[error("Failed to run script: {0}")]
+    ScriptError(String),
 
     #[error("Failed to encode protobuf message")]
     EncodeError(#[from] prost::EncodeError),
@@ -1,9 +1,15 @@
+mod codec;
 mod errors;
+mod limit;
 mod pb;
+mod resp;
 mod service;
 mod storage;
 
+pub use codec::*;
 pub use errors::KvError;
+pub use limit::*;
 pub use pb::abi::*;
+pub use resp::{RespDecoder, RespFrame, RespLimits, RespService};
 pub use service::*;
 pub use storage::*;
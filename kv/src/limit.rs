@@ -0,0 +1,333 @@
+use crate::{command_request::RequestData, CommandRequest, KvError, Value};
+use dashmap::DashMap;
+use prost::Message;
+use std::{
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+use tracing::{debug, warn};
+
+/// 令牌桶的配额
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    /// 每秒补充的令牌数
+    pub rate: f64,
+    /// 桶的容量，即允许的突发请求数
+    pub burst: u32,
+}
+
+impl Quota {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self { rate, burst }
+    }
+}
+
+/// 限流配置，没有设置的限制不生效
+#[derive(Debug, Clone, Default)]
+pub struct LimitConfig {
+    /// 每个客户端 IP 的请求速率
+    pub per_ip: Option<Quota>,
+    /// 所有连接同时处理中的最大请求数
+    pub max_in_flight: Option<usize>,
+    /// 写入的单个 value 编码后的最大字节数
+    pub max_value_size: Option<usize>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(quota: &Quota, now: Instant) -> Self {
+        Self {
+            tokens: quota.burst as f64,
+            updated_at: now,
+        }
+    }
+
+    // 根据流逝的时间补充令牌
+    fn refill(&mut self, quota: &Quota, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * quota.rate).min(quota.burst as f64);
+        self.updated_at = now;
+    }
+
+    fn has_token(&self) -> bool {
+        self.tokens >= 1.0
+    }
+
+    fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+
+    // 补满的桶和新建的桶没有区别，可以删掉
+    fn is_idle(&mut self, quota: &Quota, now: Instant) -> bool {
+        self.refill(quota, now);
+        self.tokens >= quota.burst as f64
+    }
+}
+
+// 每处理这么多次检查清理一次空闲的令牌桶
+const EVICT_INTERVAL: usize = 1024;
+
+/// 在所有连接间共享的限流器，按客户端 IP 记录令牌桶
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    inner: Arc<RateLimiterInner>,
+}
+
+#[derive(Debug, Default)]
+struct RateLimiterInner {
+    config: LimitConfig,
+    ips: DashMap<IpAddr, TokenBucket>,
+    // 所有连接上处理中的请求数
+    in_flight: Arc<AtomicUsize>,
+    checks: AtomicUsize,
+}
+
+impl RateLimiter {
+    pub fn new(config: LimitConfig) -> Self {
+        Self {
+            inner: Arc::new(RateLimiterInner {
+                config,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// 为一个新连接创建 ConnectionLimiter
+    pub fn connection(&self, ip: IpAddr) -> ConnectionLimiter {
+        ConnectionLimiter {
+            limiter: self.clone(),
+            ip,
+        }
+    }
+
+    /// 删除已经补满的令牌桶，避免长时间运行后 ips 无限增长
+    pub fn evict_idle(&self) {
+        self.evict_idle_at(Instant::now())
+    }
+
+    fn evict_idle_at(&self, now: Instant) {
+        if let Some(quota) = &self.inner.config.per_ip {
+            self.inner.ips.retain(|_, b| !b.is_idle(quota, now));
+        }
+    }
+
+    fn check_rate(&self, ip: IpAddr, now: Instant) -> Result<(), KvError> {
+        let inner = &self.inner;
+        if inner.checks.fetch_add(1, Ordering::Relaxed) % EVICT_INTERVAL == EVICT_INTERVAL - 1 {
+            self.evict_idle_at(now);
+        }
+
+        if let Some(quota) = &inner.config.per_ip {
+            let mut bucket = inner
+                .ips
+                .entry(ip)
+                .or_insert_with(|| TokenBucket::new(quota, now));
+            bucket.refill(quota, now);
+            if !bucket.has_token() {
+                warn!("Rate limited ip {}: {:?}", ip, *bucket);
+                return Err(KvError::QuotaExceeded(format!(
+                    "too many requests from {}",
+                    ip
+                )));
+            }
+            bucket.take();
+        }
+        Ok(())
+    }
+}
+
+/// 单个连接上的限流器
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    limiter: RateLimiter,
+    ip: IpAddr,
+}
+
+/// 处理中的请求，drop 时释放 in-flight 计数
+#[derive(Debug)]
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ConnectionLimiter {
+    /// 检查命令是否超出配额，通过时返回的 InFlight 需要持有到请求处理完成
+    pub fn check(&self, cmd: &CommandRequest) -> Result<InFlight, KvError> {
+        self.check_at(cmd, Instant::now())
+    }
+
+    fn check_at(&self, cmd: &CommandRequest, now: Instant) -> Result<InFlight, KvError> {
+        let config = &self.limiter.inner.config;
+
+        if let Some(max) = config.max_value_size {
+            if let Some(size) = max_value_size(cmd).filter(|size| *size > max) {
+                warn!("Value from {} is too large: {} > {}", self.ip, size, max);
+                return Err(KvError::QuotaExceeded(format!(
+                    "value size {} exceeds limit {}",
+                    size, max
+                )));
+            }
+        }
+
+        let counter = &self.limiter.inner.in_flight;
+        let in_flight = counter.fetch_add(1, Ordering::SeqCst) + 1;
+        let guard = InFlight(counter.clone());
+        if let Some(max) = config.max_in_flight {
+            if in_flight > max {
+                warn!(
+                    "Too many in-flight requests from {}: {} > {}",
+                    self.ip, in_flight, max
+                );
+                return Err(KvError::QuotaExceeded(format!(
+                    "too many in-flight requests: {}",
+                    in_flight
+                )));
+            }
+        }
+
+        self.limiter.check_rate(self.ip, now)?;
+        debug!(
+            "Accepted request from {}, in-flight: {}",
+            self.ip, in_flight
+        );
+
+        Ok(guard)
+    }
+}
+
+// 请求中最大的 value 的大小，不写入 value 的命令返回 None
+fn max_value_size(cmd: &CommandRequest) -> Option<usize> {
+    match cmd.request_data {
+        Some(RequestData::Hset(ref v)) => {
+            let pair = v.pair.as_ref()?;
+            pair.value.as_ref().map(Value::encoded_len)
+        }
+        Some(RequestData::Hmset(ref v)) => v
+            .pairs
+            .iter()
+            .filter_map(|pair| pair.value.as_ref().map(Value::encoded_len))
+            .max(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CommandResponse;
+    use http::StatusCode;
+    use std::time::Duration;
+
+    fn localhost() -> IpAddr {
+        "127.0.0.1".parse().unwrap()
+    }
+
+    #[test]
+    fn token_bucket_should_refill() {
+        let quota = Quota::new(2.0, 2);
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(&quota, now);
+        let mut try_take = |now| {
+            bucket.refill(&quota, now);
+            let ok = bucket.has_token();
+            if ok {
+                bucket.take();
+            }
+            ok
+        };
+        assert!(try_take(now));
+        assert!(try_take(now));
+        assert!(!try_take(now));
+
+        // 0.5 秒之后补充了一个令牌
+        let now = now + Duration::from_millis(500);
+        assert!(try_take(now));
+        assert!(!try_take(now));
+    }
+
+    #[test]
+    fn per_ip_limit_should_work() {
+        let limiter = RateLimiter::new(LimitConfig {
+            per_ip: Some(Quota::new(1.0, 2)),
+            ..Default::default()
+        });
+        let cmd = CommandRequest::new_hget("t1", "k1");
+        let now = Instant::now();
+
+        // 同一个 IP 的配额在所有连接间共享
+        let conn = limiter.connection(localhost());
+        assert!(conn.check_at(&cmd, now).is_ok());
+        let conn1 = limiter.connection(localhost());
+        assert!(conn1.check_at(&cmd, now).is_ok());
+        assert!(conn1.check_at(&cmd, now).is_err());
+        assert!(conn.check_at(&cmd, now).is_err());
+        // 其它 IP 不受影响
+        let conn2 = limiter.connection("10.0.0.1".parse().unwrap());
+        assert!(conn2.check_at(&cmd, now).is_ok());
+    }
+
+    #[test]
+    fn max_in_flight_should_work() {
+        let limiter = RateLimiter::new(LimitConfig {
+            max_in_flight: Some(1),
+            ..Default::default()
+        });
+        let conn = limiter.connection(localhost());
+        let cmd = CommandRequest::new_hget("t1", "k1");
+
+        let guard = conn.check(&cmd).unwrap();
+        assert!(conn.check(&cmd).is_err());
+        // 所有连接共享同一个计数
+        let conn1 = limiter.connection("10.0.0.1".parse().unwrap());
+        assert!(conn1.check(&cmd).is_err());
+        drop(guard);
+        assert!(conn1.check(&cmd).is_ok());
+    }
+
+    #[test]
+    fn idle_buckets_should_be_evicted() {
+        let limiter = RateLimiter::new(LimitConfig {
+            per_ip: Some(Quota::new(1.0, 2)),
+            ..Default::default()
+        });
+        let cmd = CommandRequest::new_hget("t1", "k1");
+        let now = Instant::now();
+
+        let conn = limiter.connection(localhost());
+        assert!(conn.check_at(&cmd, now).is_ok());
+        limiter.evict_idle_at(now);
+        assert_eq!(limiter.inner.ips.len(), 1);
+
+        // 令牌补满之后就可以删除
+        limiter.evict_idle_at(now + Duration::from_secs(1));
+        assert!(limiter.inner.ips.is_empty());
+    }
+
+    #[test]
+    fn max_value_size_should_work() {
+        let limiter = RateLimiter::new(LimitConfig {
+            max_value_size: Some(16),
+            ..Default::default()
+        });
+        let conn = limiter.connection(localhost());
+
+        let cmd = CommandRequest::new_hset("t1", "k1", "small".into());
+        assert!(conn.check(&cmd).is_ok());
+
+        let cmd = CommandRequest::new_hset("t1", "k1", "a".repeat(32).as_str().into());
+        let res: CommandResponse = conn.check(&cmd).unwrap_err().into();
+        assert_eq!(res.status, StatusCode::TOO_MANY_REQUESTS.as_u16() as u32);
+    }
+}
@@ -1,13 +1,15 @@
 /// 来自客户端的命令请求
-#[derive(PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CommandRequest {
-    #[prost(oneof="command_request::RequestData", tags="1, 2, 3, 4, 5, 6, 7, 8, 9")]
+    #[prost(oneof="command_request::RequestData", tags="1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15")]
     pub request_data: ::core::option::Option<command_request::RequestData>,
 }
 /// Nested message and enum types in `CommandRequest`.
 pub mod command_request {
-    #[derive(PartialOrd)]
+    #[derive(serde::Serialize, serde::Deserialize)]
+    #[serde(rename_all = "snake_case")]
     #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum RequestData {
         #[prost(message, tag="1")]
@@ -28,10 +30,23 @@ pub mod command_request {
         Hexist(super::Hexist),
         #[prost(message, tag="9")]
         Hmexist(super::Hmexist),
+        #[prost(message, tag="10")]
+        Dump(super::Dump),
+        #[prost(message, tag="11")]
+        Restore(super::Restore),
+        #[prost(message, tag="12")]
+        CreateIndex(super::CreateIndex),
+        #[prost(message, tag="13")]
+        FindByValue(super::FindByValue),
+        #[prost(message, tag="14")]
+        Watch(super::Watch),
+        #[prost(message, tag="15")]
+        Eval(super::Eval),
     }
 }
 /// 服务器的响应
-#[derive(PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CommandResponse {
     /// 状态码；复用 HTTP 2xx/4xx/5xx 状态码
@@ -46,9 +61,47 @@ pub struct CommandResponse {
     /// 成功返回的 kv pairs
     #[prost(message, repeated, tag="4")]
     pub pairs: ::prost::alloc::vec::Vec<Kvpair>,
+    /// Watch 返回的修改事件
+    #[prost(message, repeated, tag="5")]
+    pub events: ::prost::alloc::vec::Vec<ChangeEvent>,
+    /// 如果不是 2xx，error 里包含结构化的错误信息
+    #[prost(message, optional, tag="6")]
+    pub error: ::core::option::Option<ErrorInfo>,
+}
+/// 结构化的错误信息，客户端可以据此还原出 KvError
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ErrorInfo {
+    #[prost(enumeration="error_info::Code", tag="1")]
+    pub code: i32,
+    /// 错误相关的字段，如 table、key 等
+    #[prost(map="string, string", tag="2")]
+    pub details: ::std::collections::HashMap<::prost::alloc::string::String, ::prost::alloc::string::String>,
+}
+/// Nested message and enum types in `ErrorInfo`.
+pub mod error_info {
+    #[derive(serde::Serialize, serde::Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Code {
+        Internal = 0,
+        NotFound = 1,
+        InvalidCommand = 2,
+        ConvertError = 3,
+        StorageError = 4,
+        CodecError = 5,
+        BackupError = 6,
+        EventsExpired = 7,
+        QuotaExceeded = 8,
+        PermissionDenied = 9,
+        ScriptError = 10,
+    }
 }
 /// 从 table 中获取一个 key，返回 value
-#[derive(PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Hget {
     #[prost(string, tag="1")]
@@ -57,14 +110,16 @@ pub struct Hget {
     pub key: ::prost::alloc::string::String,
 }
 /// 从 table 中获取所有的 Kvpair
-#[derive(PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Hgetall {
     #[prost(string, tag="1")]
     pub table: ::prost::alloc::string::String,
 }
 /// 从 table 中获取一组 key，返回它们的 value
-#[derive(PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Hmget {
     #[prost(string, tag="1")]
@@ -73,14 +128,18 @@ pub struct Hmget {
     pub keys: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 /// 返回的值
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(PartialOrd)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Value {
-    #[prost(oneof="value::Value", tags="1, 2, 3, 4, 5")]
+    #[prost(oneof="value::Value", tags="1, 2, 3, 4, 5, 6")]
     pub value: ::core::option::Option<value::Value>,
 }
 /// Nested message and enum types in `Value`.
 pub mod value {
+    #[derive(serde::Serialize, serde::Deserialize)]
+    #[serde(rename_all = "snake_case")]
     #[derive(PartialOrd)]
     #[derive(Clone, PartialEq, ::prost::Oneof)]
     pub enum Value {
@@ -94,9 +153,25 @@ pub mod value {
         Float(f64),
         #[prost(bool, tag="5")]
         Bool(bool),
+        #[prost(message, tag="6")]
+        Encoded(super::Encoded),
     }
 }
+/// 使用某种编码格式序列化后的 Rust 数据结构
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(PartialOrd)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Encoded {
+    /// 序列化时使用的编码格式，解码时需要与之匹配
+    #[prost(enumeration="Codec", tag="1")]
+    pub codec: i32,
+    #[prost(bytes="bytes", tag="2")]
+    pub data: ::prost::bytes::Bytes,
+}
 /// 返回的 kvpair
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(PartialOrd)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Kvpair {
@@ -107,7 +182,8 @@ pub struct Kvpair {
 }
 /// 往 table 里存一个 kvpair，
 /// 如果 table 不存在就创建这个 table
-#[derive(PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Hset {
     #[prost(string, tag="1")]
@@ -117,7 +193,8 @@ pub struct Hset {
 }
 /// 往 table 中存一组 kvpair，
 /// 如果 table 不存在就创建这个 table
-#[derive(PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Hmset {
     #[prost(string, tag="1")]
@@ -126,7 +203,8 @@ pub struct Hmset {
     pub pairs: ::prost::alloc::vec::Vec<Kvpair>,
 }
 /// 从 table 中删除一个 key，返回它之前的值
-#[derive(PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Hdel {
     #[prost(string, tag="1")]
@@ -135,7 +213,8 @@ pub struct Hdel {
     pub key: ::prost::alloc::string::String,
 }
 /// 从 table 中删除一组 key，返回它们之前的值
-#[derive(PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Hmdel {
     #[prost(string, tag="1")]
@@ -144,7 +223,8 @@ pub struct Hmdel {
     pub keys: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 /// 查看 key 是否存在
-#[derive(PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Hexist {
     #[prost(string, tag="1")]
@@ -153,7 +233,8 @@ pub struct Hexist {
     pub key: ::prost::alloc::string::String,
 }
 /// 查看一组 key 是否存在
-#[derive(PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Hmexist {
     #[prost(string, tag="1")]
@@ -161,3 +242,162 @@ pub struct Hmexist {
     #[prost(string, repeated, tag="2")]
     pub keys: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
+/// 把一个或所有 table 的数据导出到服务器上的文件
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Dump {
+    /// 为空时导出所有 table
+    #[prost(string, tag="1")]
+    pub table: ::prost::alloc::string::String,
+    /// 相对于服务器配置的备份目录的路径
+    #[prost(string, tag="2")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(enumeration="dump::Format", tag="3")]
+    pub format: i32,
+}
+/// Nested message and enum types in `Dump`.
+pub mod dump {
+    #[derive(serde::Serialize, serde::Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Format {
+        /// length delimited 的 DumpRecord 序列
+        Protobuf = 0,
+        /// 每行一个 JSON 格式的 DumpRecord
+        Ndjson = 1,
+    }
+}
+/// 从服务器上的文件导入 Dump 导出的数据
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Restore {
+    /// 相对于服务器配置的备份目录的路径
+    #[prost(string, tag="1")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(enumeration="dump::Format", tag="2")]
+    pub format: i32,
+    #[prost(enumeration="restore::Mode", tag="3")]
+    pub mode: i32,
+}
+/// Nested message and enum types in `Restore`.
+pub mod restore {
+    #[derive(serde::Serialize, serde::Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Mode {
+        /// 保留 table 中已有的数据，相同的 key 会被覆盖
+        Merge = 0,
+        /// 导入前清空文件中出现的 table
+        Replace = 1,
+    }
+}
+/// Dump 文件中的一条记录
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DumpRecord {
+    #[prost(string, tag="1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(message, optional, tag="2")]
+    pub pair: ::core::option::Option<Kvpair>,
+}
+/// 为 table 创建从 value 到 key 的索引，写入 table 时会同步更新索引
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CreateIndex {
+    #[prost(string, tag="1")]
+    pub table: ::prost::alloc::string::String,
+}
+/// 通过索引查找 table 中 value 等于给定值的所有 kvpair
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FindByValue {
+    #[prost(string, tag="1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(message, optional, tag="2")]
+    pub value: ::core::option::Option<Value>,
+}
+/// 订阅 table 中 key 以 prefix 开头的所有修改
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Watch {
+    #[prost(string, tag="1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag="2")]
+    pub prefix: ::prost::alloc::string::String,
+    /// 从这个序号开始（包含）推送修改事件，断线后可以用最后收到的序号 + 1 继续；
+    /// 为 0 时只推送之后产生的事件
+    #[prost(uint64, tag="3")]
+    pub from_seq: u64,
+}
+/// 通过 Service 对 table 的一次修改
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChangeEvent {
+    /// 全局递增的序号，从 1 开始
+    #[prost(uint64, tag="1")]
+    pub seq: u64,
+    #[prost(enumeration="change_event::Kind", tag="2")]
+    pub kind: i32,
+    #[prost(string, tag="3")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag="4")]
+    pub key: ::prost::alloc::string::String,
+    /// 修改前的值，之前不存在时为空
+    #[prost(message, optional, tag="5")]
+    pub old_value: ::core::option::Option<Value>,
+    /// 修改后的值，删除时为空
+    #[prost(message, optional, tag="6")]
+    pub new_value: ::core::option::Option<Value>,
+}
+/// Nested message and enum types in `ChangeEvent`.
+pub mod change_event {
+    #[derive(serde::Serialize, serde::Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Kind {
+        Set = 0,
+        Del = 1,
+    }
+}
+/// 在 server 上执行 Rhai 脚本，脚本只能访问 keys 中声明的 key
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Eval {
+    #[prost(string, tag="1")]
+    pub script: ::prost::alloc::string::String,
+    #[prost(message, repeated, tag="2")]
+    pub keys: ::prost::alloc::vec::Vec<EvalKey>,
+    /// 脚本中通过 ARGS 访问的参数
+    #[prost(message, repeated, tag="3")]
+    pub args: ::prost::alloc::vec::Vec<Value>,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EvalKey {
+    #[prost(string, tag="1")]
+    pub table: ::prost::alloc::string::String,
+    #[prost(string, tag="2")]
+    pub key: ::prost::alloc::string::String,
+}
+/// 支持的编码格式
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Codec {
+    Json = 0,
+    Bincode = 1,
+    Protobuf = 2,
+}
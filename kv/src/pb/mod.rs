@@ -89,6 +89,78 @@ impl CommandRequest {
             })),
         }
     }
+
+    /// table 为空时导出所有 table
+    pub fn new_dump(
+        table: impl Into<String>,
+        path: impl Into<String>,
+        format: dump::Format,
+    ) -> Self {
+        Self {
+            request_data: Some(RequestData::Dump(Dump {
+                table: table.into(),
+                path: path.into(),
+                format: format as _,
+            })),
+        }
+    }
+
+    pub fn new_restore(path: impl Into<String>, format: dump::Format, mode: restore::Mode) -> Self {
+        Self {
+            request_data: Some(RequestData::Restore(Restore {
+                path: path.into(),
+                format: format as _,
+                mode: mode as _,
+            })),
+        }
+    }
+
+    pub fn new_create_index(table: impl Into<String>) -> Self {
+        Self {
+            request_data: Some(RequestData::CreateIndex(CreateIndex {
+                table: table.into(),
+            })),
+        }
+    }
+
+    pub fn new_find_by_value(table: impl Into<String>, value: Value) -> Self {
+        Self {
+            request_data: Some(RequestData::FindByValue(FindByValue {
+                table: table.into(),
+                value: Some(value),
+            })),
+        }
+    }
+
+    /// from_seq 为 0 时只接收之后产生的修改事件
+    pub fn new_watch(table: impl Into<String>, prefix: impl Into<String>, from_seq: u64) -> Self {
+        Self {
+            request_data: Some(RequestData::Watch(Watch {
+                table: table.into(),
+                prefix: prefix.into(),
+                from_seq,
+            })),
+        }
+    }
+
+    pub fn new_eval(script: impl Into<String>, keys: Vec<EvalKey>, args: Vec<Value>) -> Self {
+        Self {
+            request_data: Some(RequestData::Eval(Eval {
+                script: script.into(),
+                keys,
+                args,
+            })),
+        }
+    }
+}
+
+impl EvalKey {
+    pub fn new(table: impl Into<String>, key: impl Into<String>) -> Self {
+        Self {
+            table: table.into(),
+            key: key.into(),
+        }
+    }
 }
 
 impl Kvpair {
@@ -150,23 +222,156 @@ impl From<Vec<Kvpair>> for CommandResponse {
     }
 }
 
+/// 从 Vec<ChangeEvent> 转换成 CommandResponse
+impl From<Vec<ChangeEvent>> for CommandResponse {
+    fn from(v: Vec<ChangeEvent>) -> Self {
+        Self {
+            status: StatusCode::OK.as_u16() as _,
+            events: v,
+            ..Default::default()
+        }
+    }
+}
+
 /// 从 KvError 转换成 CommandResponse
 impl From<KvError> for CommandResponse {
     fn from(e: KvError) -> Self {
-        let mut result = Self {
-            status: StatusCode::INTERNAL_SERVER_ERROR.as_u16() as _,
+        let status = match e {
+            KvError::NotFound(_, _) => StatusCode::NOT_FOUND,
+            KvError::InvalidCommand(_) | KvError::ScriptError(_) => StatusCode::BAD_REQUEST,
+            KvError::QuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+            KvError::EventsExpired(_, _) => StatusCode::GONE,
+            KvError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        Self {
+            status: status.as_u16() as _,
             message: e.to_string(),
-            values: vec![],
-            pairs: vec![],
+            error: Some((&e).into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// 从 KvError 转换成结构化的 ErrorInfo
+impl From<&KvError> for ErrorInfo {
+    fn from(e: &KvError) -> Self {
+        use error_info::Code;
+
+        let (code, details) = match e {
+            KvError::NotFound(table, key) => (
+                Code::NotFound,
+                vec![("table", table.clone()), ("key", key.clone())],
+            ),
+            KvError::InvalidCommand(reason) => {
+                (Code::InvalidCommand, vec![("reason", reason.clone())])
+            }
+            KvError::ConvertError(value, target) => (
+                Code::ConvertError,
+                vec![
+                    ("value", serde_json::to_string(value).unwrap_or_default()),
+                    ("target", target.clone()),
+                ],
+            ),
+            KvError::StorageError(op, table, key, error) => (
+                Code::StorageError,
+                vec![
+                    ("op", op.clone()),
+                    ("table", table.clone()),
+                    ("key", key.clone()),
+                    ("error", error.clone()),
+                ],
+            ),
+            KvError::CodecError(op, codec, error) => (
+                Code::CodecError,
+                vec![
+                    ("op", op.clone()),
+                    ("codec", (*codec as i32).to_string()),
+                    ("error", error.clone()),
+                ],
+            ),
+            KvError::BackupError(op, path, error) => (
+                Code::BackupError,
+                vec![
+                    ("op", op.clone()),
+                    ("path", path.clone()),
+                    ("error", error.clone()),
+                ],
+            ),
+            KvError::EventsExpired(from_seq, oldest_seq) => (
+                Code::EventsExpired,
+                vec![
+                    ("from_seq", from_seq.to_string()),
+                    ("oldest_seq", oldest_seq.to_string()),
+                ],
+            ),
+            KvError::QuotaExceeded(reason) => {
+                (Code::QuotaExceeded, vec![("reason", reason.clone())])
+            }
+            KvError::PermissionDenied(reason) => {
+                (Code::PermissionDenied, vec![("reason", reason.clone())])
+            }
+            KvError::ScriptError(error) => (Code::ScriptError, vec![("error", error.clone())]),
+            KvError::Internal(error) => (Code::Internal, vec![("error", error.clone())]),
+            // protobuf 和 sled 的错误无法在客户端还原，只保留错误信息
+            _ => (Code::Internal, vec![("error", e.to_string())]),
         };
 
-        match e {
-            KvError::NotFound(_, _) => result.status = StatusCode::NOT_FOUND.as_u16() as _,
-            KvError::InvalidCommand(_) => result.status = StatusCode::BAD_REQUEST.as_u16() as _,
-            _ => {}
+        Self {
+            code: code as _,
+            details: details.into_iter().map(|(k, v)| (k.into(), v)).collect(),
         }
+    }
+}
 
-        result
+/// 从 ErrorInfo 还原出 KvError
+impl From<ErrorInfo> for KvError {
+    fn from(mut info: ErrorInfo) -> Self {
+        use error_info::Code;
+
+        let code = info.code();
+        let mut take = |k: &str| info.details.remove(k).unwrap_or_default();
+        match code {
+            Code::NotFound => KvError::NotFound(take("table"), take("key")),
+            Code::InvalidCommand => KvError::InvalidCommand(take("reason")),
+            Code::ConvertError => KvError::ConvertError(
+                serde_json::from_str(&take("value")).unwrap_or_default(),
+                take("target"),
+            ),
+            Code::StorageError => {
+                KvError::StorageError(take("op"), take("table"), take("key"), take("error"))
+            }
+            Code::CodecError => KvError::CodecError(
+                take("op"),
+                Codec::from_i32(take("codec").parse().unwrap_or_default()).unwrap_or_default(),
+                take("error"),
+            ),
+            Code::BackupError => KvError::BackupError(take("op"), take("path"), take("error")),
+            Code::EventsExpired => KvError::EventsExpired(
+                take("from_seq").parse().unwrap_or_default(),
+                take("oldest_seq").parse().unwrap_or_default(),
+            ),
+            Code::QuotaExceeded => KvError::QuotaExceeded(take("reason")),
+            Code::PermissionDenied => KvError::PermissionDenied(take("reason")),
+            Code::ScriptError => KvError::ScriptError(take("error")),
+            Code::Internal => KvError::Internal(take("error")),
+        }
+    }
+}
+
+impl CommandResponse {
+    /// 客户端把响应转换成 Result，出错时根据 error 还原出 KvError
+    pub fn into_result(self) -> Result<Self, KvError> {
+        if let Some(info) = self.error {
+            return Err(info.into());
+        }
+
+        match StatusCode::from_u16(self.status as _) {
+            Ok(status) if status.is_success() => Ok(self),
+            // 没有返回 ErrorInfo 的 server 只能使用 message
+            _ => Err(KvError::Internal(self.message)),
+        }
     }
 }
 
@@ -180,6 +385,16 @@ impl From<Vec<Value>> for CommandResponse {
     }
 }
 
+/// 从 Result<Vec<Value>, KvError> 转换成 CommandResponse
+impl From<Result<Vec<Value>, KvError>> for CommandResponse {
+    fn from(r: Result<Vec<Value>, KvError>) -> Self {
+        match r {
+            Ok(v) => v.into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
 impl From<bool> for Value {
     fn from(b: bool) -> Self {
         Self {
@@ -202,7 +417,7 @@ impl TryFrom<Value> for i64 {
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v.value {
             Some(value::Value::Integer(i)) => Ok(i),
-            _ => Err(KvError::ConvertError(v, "Integer")),
+            _ => Err(KvError::ConvertError(v, "Integer".into())),
         }
     }
 }
@@ -213,7 +428,7 @@ impl TryFrom<Value> for f64 {
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v.value {
             Some(value::Value::Float(f)) => Ok(f),
-            _ => Err(KvError::ConvertError(v, "Float")),
+            _ => Err(KvError::ConvertError(v, "Float".into())),
         }
     }
 }
@@ -224,7 +439,7 @@ impl TryFrom<Value> for Bytes {
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v.value {
             Some(value::Value::Binary(b)) => Ok(b),
-            _ => Err(KvError::ConvertError(v, "Binary")),
+            _ => Err(KvError::ConvertError(v, "Binary".into())),
         }
     }
 }
@@ -235,7 +450,7 @@ impl TryFrom<Value> for bool {
     fn try_from(v: Value) -> Result<Self, Self::Error> {
         match v.value {
             Some(value::Value::Bool(b)) => Ok(b),
-            _ => Err(KvError::ConvertError(v, "Boolean")),
+            _ => Err(KvError::ConvertError(v, "Boolean".into())),
         }
     }
 }
@@ -257,3 +472,56 @@ impl TryFrom<&[u8]> for Value {
         Ok(msg)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_request_json_should_work() {
+        let json = r#"{"request_data":{"hget":{"table":"t1","key":"hello"}}}"#;
+        let cmd: CommandRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(cmd, CommandRequest::new_hget("t1", "hello"));
+        assert_eq!(serde_json::to_string(&cmd).unwrap(), json);
+    }
+
+    #[test]
+    fn error_should_be_restored_from_response() {
+        let errors: Vec<fn() -> KvError> = vec![
+            || KvError::NotFound("t1".into(), "k1".into()),
+            || KvError::InvalidCommand("bad".into()),
+            || KvError::ConvertError(10.into(), "Binary".into()),
+            || KvError::StorageError("decode".into(), "t1".into(), "k1".into(), "oops".into()),
+            || KvError::CodecError("decode".into(), Codec::Bincode, "oops".into()),
+            || KvError::BackupError("dump".into(), "/tmp/dump".into(), "oops".into()),
+            || KvError::EventsExpired(1, 10),
+            || KvError::QuotaExceeded("too many".into()),
+            || KvError::PermissionDenied("t1".into()),
+            || KvError::ScriptError("oops".into()),
+            || KvError::Internal("oops".into()),
+        ];
+
+        for e in errors {
+            let res: CommandResponse = e().into();
+            // 经过网络传输之后依然可以还原
+            let res = CommandResponse::decode(res.encode_to_vec().as_slice()).unwrap();
+            assert_eq!(res.into_result(), Err(e()));
+        }
+    }
+
+    #[test]
+    fn untyped_error_should_fallback_to_internal() {
+        let res = CommandResponse {
+            status: 404,
+            message: "Not found".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            res.into_result(),
+            Err(KvError::Internal("Not found".into()))
+        );
+
+        let res: CommandResponse = Value::from(1).into();
+        assert!(res.into_result().is_ok());
+    }
+}
@@ -0,0 +1,732 @@
+use crate::{
+    command_request::RequestData, error_info::Code, value, CommandRequest, CommandResponse,
+    ConnectionLimiter, ErrorInfo, KvError, Kvpair, MemTable, Service, Storage, Value,
+};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::convert::TryFrom;
+
+const CRLF: &[u8] = b"\r\n";
+// 防止恶意的数组长度一次性申请过多内存
+const MAX_PREALLOC: usize = 1024;
+
+/// 解析 RESP 数据时的限制，防止恶意客户端耗尽内存或栈空间
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RespLimits {
+    /// bulk string 的最大长度，和 Redis 的 proto-max-bulk-len 一致
+    pub max_bulk_len: usize,
+    /// 一行（包括 inline command）的最大长度
+    pub max_line_len: usize,
+    /// 数组的最大嵌套层数
+    pub max_depth: usize,
+}
+
+impl Default for RespLimits {
+    fn default() -> Self {
+        Self {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_line_len: 64 * 1024,
+            max_depth: 32,
+        }
+    }
+}
+
+/// RESP2 协议的数据帧
+#[derive(Debug, Clone, PartialEq)]
+pub enum RespFrame {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Bytes>),
+    Array(Option<Vec<RespFrame>>),
+}
+
+impl RespFrame {
+    /// 从 buf 中解析出一个完整的 frame 并消费对应的数据，数据不完整时返回 None
+    ///
+    /// 每次调用都从头解析 buf，在连接上分多次读取数据时应该使用 RespDecoder
+    pub fn decode(buf: &mut BytesMut) -> Result<Option<Self>, KvError> {
+        Self::decode_with_limits(buf, &RespLimits::default())
+    }
+
+    /// 和 decode 一样，但使用指定的限制
+    pub fn decode_with_limits(
+        buf: &mut BytesMut,
+        limits: &RespLimits,
+    ) -> Result<Option<Self>, KvError> {
+        RespDecoder::new(*limits).decode(buf)
+    }
+
+    /// 把 frame 编码后写入 buf
+    pub fn encode(&self, buf: &mut BytesMut) {
+        match self {
+            RespFrame::Simple(s) => put_line(buf, b'+', s.as_bytes()),
+            RespFrame::Error(s) => put_line(buf, b'-', s.as_bytes()),
+            RespFrame::Integer(i) => put_line(buf, b':', i.to_string().as_bytes()),
+            RespFrame::Bulk(None) => put_line(buf, b'$', b"-1"),
+            RespFrame::Bulk(Some(data)) => {
+                put_line(buf, b'$', data.len().to_string().as_bytes());
+                buf.put_slice(data);
+                buf.put_slice(CRLF);
+            }
+            RespFrame::Array(None) => put_line(buf, b'*', b"-1"),
+            RespFrame::Array(Some(frames)) => {
+                put_line(buf, b'*', frames.len().to_string().as_bytes());
+                frames.iter().for_each(|f| f.encode(buf));
+            }
+        }
+    }
+
+    /// 把客户端发来的命令拆分成参数列表
+    fn into_args(self) -> Result<Vec<Bytes>, KvError> {
+        match self {
+            RespFrame::Array(Some(frames)) if !frames.is_empty() => frames
+                .into_iter()
+                .map(|f| match f {
+                    RespFrame::Bulk(Some(data)) => Ok(data),
+                    f => Err(protocol_error(format!("expect bulk string, got {:?}", f))),
+                })
+                .collect(),
+            f => Err(protocol_error(format!(
+                "expect array of bulk strings, got {:?}",
+                f
+            ))),
+        }
+    }
+}
+
+/// 连接上的 RESP 解码器，数据不完整时记住已经解析的部分，收到更多数据后从中断的位置继续解析
+///
+/// 两次 decode 之间只能往 buf 末尾追加数据
+#[derive(Debug, Default)]
+pub struct RespDecoder {
+    limits: RespLimits,
+    // buf[..pos] 已经解析过
+    pos: usize,
+    // 还没有解析完的数组，以及数组的长度
+    arrays: Vec<(Vec<RespFrame>, usize)>,
+}
+
+// parse 一次解析出的内容
+enum Item {
+    Frame(RespFrame),
+    // 数组的开头，元素需要继续解析
+    Array(usize),
+}
+
+impl RespDecoder {
+    pub fn new(limits: RespLimits) -> Self {
+        Self {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    /// 从 buf 中解析出一个完整的 frame 并消费对应的数据，数据不完整时返回 None
+    pub fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<RespFrame>, KvError> {
+        let result = self.try_decode(buf);
+        if result.is_err() {
+            self.pos = 0;
+            self.arrays.clear();
+        }
+        result
+    }
+
+    fn try_decode(&mut self, buf: &mut BytesMut) -> Result<Option<RespFrame>, KvError> {
+        loop {
+            let (item, next) = match parse(buf, self.pos, &self.limits, self.arrays.len())? {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+            self.pos = next;
+
+            let mut frame = match item {
+                Item::Frame(frame) => frame,
+                Item::Array(len) => {
+                    let frames = Vec::with_capacity(len.min(MAX_PREALLOC));
+                    self.arrays.push((frames, len));
+                    continue;
+                }
+            };
+            // 把解析出的 frame 放进所在的数组，数组满了之后继续放进上一层数组
+            loop {
+                match self.arrays.last_mut() {
+                    Some((frames, len)) => {
+                        frames.push(frame);
+                        if frames.len() < *len {
+                            break;
+                        }
+                        frame = RespFrame::Array(self.arrays.pop().map(|(frames, _)| frames));
+                    }
+                    None => {
+                        buf.advance(self.pos);
+                        self.pos = 0;
+                        return Ok(Some(frame));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 从 RESP 命令转换成 CommandRequest，支持 HGET/HSET/HGETALL/HMGET/HDEL/HEXISTS
+impl TryFrom<RespFrame> for CommandRequest {
+    type Error = KvError;
+
+    fn try_from(frame: RespFrame) -> Result<Self, Self::Error> {
+        parse_command(frame.into_args()?)
+    }
+}
+
+/// 从 KvError 转换成 RESP 的错误回复，错误前缀由 ErrorInfo 中的错误码决定
+impl From<KvError> for RespFrame {
+    fn from(e: KvError) -> Self {
+        let prefix = match ErrorInfo::from(&e).code() {
+            Code::ConvertError | Code::CodecError => "WRONGTYPE",
+            Code::PermissionDenied => "NOPERM",
+            Code::NotFound => "NOTFOUND",
+            Code::StorageError => "STORAGE",
+            Code::BackupError => "BACKUP",
+            Code::EventsExpired => "EXPIRED",
+            Code::QuotaExceeded => "QUOTA",
+            Code::ScriptError => "SCRIPT",
+            Code::InvalidCommand | Code::Internal => "ERR",
+        };
+        RespFrame::Error(format!("{} {}", prefix, e))
+    }
+}
+
+/// 在 Service 之上处理 RESP 命令
+pub struct RespService<Store = MemTable> {
+    inner: Service<Store>,
+}
+
+impl<Store> Clone for RespService<Store> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<Store: Storage> RespService<Store> {
+    pub fn new(service: Service<Store>) -> Self {
+        Self { inner: service }
+    }
+
+    /// 处理一个 RESP 命令，返回 RESP 回复
+    pub fn execute(&self, frame: RespFrame) -> RespFrame {
+        self.run(frame, None)
+    }
+
+    /// 和 execute 一样，但先检查连接的配额
+    pub fn execute_limited(&self, frame: RespFrame, limiter: &ConnectionLimiter) -> RespFrame {
+        self.run(frame, Some(limiter))
+    }
+
+    fn run(&self, frame: RespFrame, limiter: Option<&ConnectionLimiter>) -> RespFrame {
+        let args = match frame.into_args() {
+            Ok(args) => args,
+            Err(e) => return e.into(),
+        };
+
+        // 很多 Redis 客户端用 PING 检查连接是否可用
+        if args[0].eq_ignore_ascii_case(b"PING") {
+            return RespFrame::Simple("PONG".into());
+        }
+
+        let cmd = match parse_command(args) {
+            Ok(cmd) => cmd,
+            Err(e) => return e.into(),
+        };
+        let _guard = match limiter.map(|l| l.check(&cmd)).transpose() {
+            Ok(guard) => guard,
+            Err(e) => return e.into(),
+        };
+        let kind = ReplyKind::from(&cmd);
+        kind.reply(self.inner.execute(cmd))
+    }
+}
+
+/// 不同的 Redis 命令对应不同形式的回复
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReplyKind {
+    // 单个 bulk string，不存在时返回 nil
+    Value,
+    // bulk string 数组
+    Values,
+    // key/value 交替排列的数组
+    Pairs,
+    // 新增的 key 数量
+    Added,
+    // 删除的 key 数量
+    Deleted,
+    // key 是否存在，返回 1 或 0
+    Exists,
+}
+
+impl From<&CommandRequest> for ReplyKind {
+    fn from(cmd: &CommandRequest) -> Self {
+        match cmd.request_data {
+            Some(RequestData::Hget(_)) => ReplyKind::Value,
+            Some(RequestData::Hmget(_)) => ReplyKind::Values,
+            Some(RequestData::Hgetall(_)) => ReplyKind::Pairs,
+            Some(RequestData::Hset(_)) | Some(RequestData::Hmset(_)) => ReplyKind::Added,
+            Some(RequestData::Hdel(_)) | Some(RequestData::Hmdel(_)) => ReplyKind::Deleted,
+            Some(RequestData::Hexist(_)) => ReplyKind::Exists,
+            _ => ReplyKind::Values,
+        }
+    }
+}
+
+impl ReplyKind {
+    fn reply(self, res: CommandResponse) -> RespFrame {
+        let res = match res.into_result() {
+            Ok(res) => res,
+            Err(KvError::NotFound(_, _)) if self == ReplyKind::Value => {
+                return RespFrame::Bulk(None)
+            }
+            Err(e) => return e.into(),
+        };
+
+        match self {
+            ReplyKind::Value => value_to_bulk(res.values.into_iter().next().unwrap_or_default()),
+            ReplyKind::Values => {
+                RespFrame::Array(Some(res.values.into_iter().map(value_to_bulk).collect()))
+            }
+            ReplyKind::Pairs => RespFrame::Array(Some(
+                res.pairs
+                    .into_iter()
+                    .flat_map(|Kvpair { key, value }| {
+                        [
+                            RespFrame::Bulk(Some(key.into())),
+                            value_to_bulk(value.unwrap_or_default()),
+                        ]
+                    })
+                    .collect(),
+            )),
+            ReplyKind::Added => {
+                RespFrame::Integer(res.values.iter().filter(|v| v.value.is_none()).count() as _)
+            }
+            ReplyKind::Deleted => {
+                RespFrame::Integer(res.values.iter().filter(|v| v.value.is_some()).count() as _)
+            }
+            ReplyKind::Exists => {
+                let exists = matches!(
+                    res.values.first(),
+                    Some(Value {
+                        value: Some(value::Value::Bool(true))
+                    })
+                );
+                RespFrame::Integer(exists as _)
+            }
+        }
+    }
+}
+
+fn parse_command(args: Vec<Bytes>) -> Result<CommandRequest, KvError> {
+    let mut args = args.into_iter();
+    let name = match args.next() {
+        Some(name) => String::from_utf8_lossy(&name).to_ascii_uppercase(),
+        None => return Err(KvError::InvalidCommand("empty command".into())),
+    };
+    let args: Vec<Bytes> = args.collect();
+
+    let cmd = match (name.as_str(), args.len()) {
+        ("HGET", 2) => CommandRequest::new_hget(to_string(&args[0])?, to_string(&args[1])?),
+        ("HGETALL", 1) => CommandRequest::new_hgetall(to_string(&args[0])?),
+        ("HMGET", n) if n >= 2 => {
+            CommandRequest::new_hmget(to_string(&args[0])?, to_strings(&args[1..])?)
+        }
+        ("HSET", 3) => CommandRequest::new_hset(
+            to_string(&args[0])?,
+            to_string(&args[1])?,
+            bulk_to_value(args[2].clone()),
+        ),
+        ("HSET", n) if n > 3 && n % 2 == 1 => {
+            let pairs = args[1..]
+                .chunks(2)
+                .map(|kv| {
+                    Ok(Kvpair::new(
+                        to_string(&kv[0])?,
+                        bulk_to_value(kv[1].clone()),
+                    ))
+                })
+                .collect::<Result<_, KvError>>()?;
+            CommandRequest::new_hmset(to_string(&args[0])?, pairs)
+        }
+        ("HDEL", 2) => CommandRequest::new_hdel(to_string(&args[0])?, to_string(&args[1])?),
+        ("HDEL", n) if n > 2 => {
+            CommandRequest::new_hmdel(to_string(&args[0])?, to_strings(&args[1..])?)
+        }
+        ("HEXISTS", 2) => CommandRequest::new_hexist(to_string(&args[0])?, to_string(&args[1])?),
+        ("HGET", _)
+        | ("HGETALL", _)
+        | ("HMGET", _)
+        | ("HSET", _)
+        | ("HDEL", _)
+        | ("HEXISTS", _) => {
+            return Err(KvError::InvalidCommand(format!(
+                "wrong number of arguments for '{}' command",
+                name.to_lowercase()
+            )))
+        }
+        _ => {
+            return Err(KvError::InvalidCommand(format!(
+                "unknown command '{}'",
+                name.to_lowercase()
+            )))
+        }
+    };
+
+    Ok(cmd)
+}
+
+// 从 buf[pos..] 解析一个 frame 或者数组的开头，返回解析出的内容以及它在 buf 中的结束位置
+// depth 是外层还没有解析完的数组的层数
+fn parse(
+    buf: &[u8],
+    pos: usize,
+    limits: &RespLimits,
+    depth: usize,
+) -> Result<Option<(Item, usize)>, KvError> {
+    let (line, next) = match read_line(buf, pos, limits.max_line_len)? {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    if line.is_empty() {
+        return Err(protocol_error("empty line"));
+    }
+
+    let frame = match line[0] {
+        b'+' => RespFrame::Simple(String::from_utf8_lossy(&line[1..]).into()),
+        b'-' => RespFrame::Error(String::from_utf8_lossy(&line[1..]).into()),
+        b':' => RespFrame::Integer(parse_int(&line[1..])?),
+        b'$' => {
+            let len = parse_int(&line[1..])?;
+            if len < 0 {
+                return Ok(Some((Item::Frame(RespFrame::Bulk(None)), next)));
+            }
+            if len as usize > limits.max_bulk_len {
+                return Err(protocol_error("invalid bulk length"));
+            }
+            let end = next + len as usize;
+            if buf.len() < end + CRLF.len() {
+                return Ok(None);
+            }
+            if &buf[end..end + CRLF.len()] != CRLF {
+                return Err(protocol_error("bulk string is not terminated by CRLF"));
+            }
+            let data = Bytes::copy_from_slice(&buf[next..end]);
+            return Ok(Some((
+                Item::Frame(RespFrame::Bulk(Some(data))),
+                end + CRLF.len(),
+            )));
+        }
+        b'*' => {
+            let len = parse_int(&line[1..])?;
+            if len < 0 {
+                return Ok(Some((Item::Frame(RespFrame::Array(None)), next)));
+            }
+            if depth >= limits.max_depth {
+                return Err(protocol_error("too many nested arrays"));
+            }
+            if len == 0 {
+                return Ok(Some((Item::Frame(RespFrame::Array(Some(vec![]))), next)));
+            }
+            return Ok(Some((Item::Array(len as usize), next)));
+        }
+        // 兼容 telnet 等工具发送的 inline command
+        _ => RespFrame::Array(Some(
+            line.split(|c| c.is_ascii_whitespace())
+                .filter(|s| !s.is_empty())
+                .map(|s| RespFrame::Bulk(Some(Bytes::copy_from_slice(s))))
+                .collect(),
+        )),
+    };
+
+    Ok(Some((Item::Frame(frame), next)))
+}
+
+// 从 buf[pos..] 读取一行（不包含 CRLF），返回这一行以及下一行的起始位置
+// 超过 max_len 还没有读到 CRLF 时返回错误，避免 buf 无限增长
+fn read_line(buf: &[u8], pos: usize, max_len: usize) -> Result<Option<(&[u8], usize)>, KvError> {
+    match buf[pos..].windows(CRLF.len()).position(|w| w == CRLF) {
+        Some(i) if i > max_len => Err(protocol_error("too big line")),
+        Some(i) => Ok(Some((&buf[pos..pos + i], pos + i + CRLF.len()))),
+        None if buf.len() - pos > max_len => Err(protocol_error("too big line")),
+        None => Ok(None),
+    }
+}
+
+fn put_line(buf: &mut BytesMut, prefix: u8, data: &[u8]) {
+    buf.put_u8(prefix);
+    buf.put_slice(data);
+    buf.put_slice(CRLF);
+}
+
+fn parse_int(data: &[u8]) -> Result<i64, KvError> {
+    std::str::from_utf8(data)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| protocol_error("invalid integer"))
+}
+
+fn to_string(data: &Bytes) -> Result<String, KvError> {
+    String::from_utf8(data.to_vec())
+        .map_err(|_| KvError::InvalidCommand("table and key must be valid utf8".into()))
+}
+
+fn to_strings(data: &[Bytes]) -> Result<Vec<String>, KvError> {
+    data.iter().map(to_string).collect()
+}
+
+// Redis 中的值都是二进制安全的字符串，合法的 utf8 存为 String，否则存为 Binary
+fn bulk_to_value(data: Bytes) -> Value {
+    match std::str::from_utf8(&data) {
+        Ok(s) => s.into(),
+        Err(_) => Value {
+            value: Some(value::Value::Binary(data)),
+        },
+    }
+}
+
+fn value_to_bulk(v: Value) -> RespFrame {
+    let data = match v.value {
+        None => return RespFrame::Bulk(None),
+        Some(value::Value::String(s)) => s.into(),
+        Some(value::Value::Binary(b)) => b,
+        Some(value::Value::Integer(i)) => i.to_string().into(),
+        Some(value::Value::Float(f)) => f.to_string().into(),
+        Some(value::Value::Bool(b)) => b.to_string().into(),
+        Some(value::Value::Encoded(e)) => e.data,
+    };
+    RespFrame::Bulk(Some(data))
+}
+
+fn protocol_error(msg: impl Into<String>) -> KvError {
+    KvError::InvalidCommand(format!("Protocol error: {}", msg.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LimitConfig, Quota, RateLimiter, ServiceInner};
+
+    #[test]
+    fn resp_frame_encode_decode_should_work() {
+        let frames = vec![
+            RespFrame::Simple("OK".into()),
+            RespFrame::Error("ERR oops".into()),
+            RespFrame::Integer(-42),
+            RespFrame::Bulk(None),
+            RespFrame::Bulk(Some("hello\r\nworld".into())),
+            RespFrame::Array(None),
+            RespFrame::Array(Some(vec![
+                RespFrame::Integer(1),
+                RespFrame::Bulk(Some("a".into())),
+            ])),
+        ];
+
+        let mut buf = BytesMut::new();
+        frames.iter().for_each(|f| f.encode(&mut buf));
+        for frame in frames {
+            assert_eq!(RespFrame::decode(&mut buf), Ok(Some(frame)));
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn resp_frame_decode_partial_data_should_wait() {
+        let data = b"*2\r\n$4\r\nHGET\r\n$2\r\nt";
+        let mut buf = BytesMut::from(&data[..]);
+        assert_eq!(RespFrame::decode(&mut buf), Ok(None));
+        // 数据不完整时不会消费 buf
+        assert_eq!(buf.len(), data.len());
+
+        buf.extend_from_slice(b"1\r\n");
+        let frame = RespFrame::decode(&mut buf).unwrap().unwrap();
+        assert_eq!(frame, command(&["HGET", "t1"]));
+    }
+
+    #[test]
+    fn resp_decoder_should_resume_from_partial_data() {
+        let mut data = BytesMut::new();
+        command(&["HSET", "t1", "k1", "v1"]).encode(&mut data);
+        RespFrame::Array(Some(vec![
+            RespFrame::Array(Some(vec![])),
+            RespFrame::Integer(1),
+        ]))
+        .encode(&mut data);
+
+        // 每次只收到一个字节
+        let mut decoder = RespDecoder::default();
+        let mut buf = BytesMut::new();
+        let mut frames = vec![];
+        for b in data.iter() {
+            buf.extend_from_slice(&[*b]);
+            if let Some(frame) = decoder.decode(&mut buf).unwrap() {
+                frames.push(frame);
+            }
+            // 已经解析出的元素不会再解析，只剩下最后一个不完整的元素
+            assert!(buf.len() - decoder.pos < b"$4\r\nHSET\r\n".len());
+        }
+        assert_eq!(
+            frames,
+            vec![
+                command(&["HSET", "t1", "k1", "v1"]),
+                RespFrame::Array(Some(vec![
+                    RespFrame::Array(Some(vec![])),
+                    RespFrame::Integer(1)
+                ])),
+            ]
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn resp_frame_decode_inline_command_should_work() {
+        let mut buf = BytesMut::from(&b"hget t1  k1\r\n"[..]);
+        let frame = RespFrame::decode(&mut buf).unwrap().unwrap();
+        assert_eq!(frame, command(&["hget", "t1", "k1"]));
+    }
+
+    #[test]
+    fn resp_frame_decode_should_respect_limits() {
+        let limits = RespLimits {
+            max_bulk_len: 4,
+            max_line_len: 8,
+            max_depth: 2,
+        };
+        let decode =
+            |data: &[u8]| RespFrame::decode_with_limits(&mut BytesMut::from(data), &limits);
+
+        assert!(decode(b"$4\r\nabcd\r\n").unwrap().is_some());
+        assert!(decode(b"$5\r\nabcde\r\n").is_err());
+        // 还没收到数据时就拒绝过大的 bulk string
+        assert!(decode(b"$536870913\r\n").is_err());
+
+        assert!(decode(b"*1\r\n*1\r\n:1\r\n").unwrap().is_some());
+        assert!(decode(b"*1\r\n*1\r\n*1\r\n:1\r\n").is_err());
+
+        // 没有 CRLF 的 inline command 超过长度后返回错误
+        assert_eq!(decode(b"hget t1"), Ok(None));
+        assert!(decode(b"hget t1 k1").is_err());
+        assert!(decode(b"hget t1 k1\r\n").is_err());
+
+        let mut buf = BytesMut::from(&b"$536870913\r\n"[..]);
+        assert!(RespFrame::decode(&mut buf).is_err());
+        let mut buf = BytesMut::from(&b"*1\r\n".repeat(33)[..]);
+        assert!(RespFrame::decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn resp_command_should_convert_to_command_request() {
+        let cmd: CommandRequest = command(&["hget", "t1", "k1"]).try_into().unwrap();
+        assert_eq!(cmd, CommandRequest::new_hget("t1", "k1"));
+
+        let cmd: CommandRequest = command(&["HSET", "t1", "k1", "v1", "k2", "v2"])
+            .try_into()
+            .unwrap();
+        let pairs = vec![
+            Kvpair::new("k1", "v1".into()),
+            Kvpair::new("k2", "v2".into()),
+        ];
+        assert_eq!(cmd, CommandRequest::new_hmset("t1", pairs));
+
+        let res = CommandRequest::try_from(command(&["HSET", "t1", "k1"]));
+        assert!(matches!(res, Err(KvError::InvalidCommand(_))));
+        let res = CommandRequest::try_from(command(&["SET", "k1", "v1"]));
+        assert!(matches!(res, Err(KvError::InvalidCommand(_))));
+    }
+
+    #[test]
+    fn resp_service_should_work() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        let resp = RespService::new(service);
+
+        let res = resp.execute(command(&["HSET", "t1", "k1", "v1", "k2", "v2"]));
+        assert_eq!(res, RespFrame::Integer(2));
+        let res = resp.execute(command(&["HSET", "t1", "k1", "v11"]));
+        assert_eq!(res, RespFrame::Integer(0));
+
+        let res = resp.execute(command(&["HGET", "t1", "k1"]));
+        assert_eq!(res, RespFrame::Bulk(Some("v11".into())));
+        let res = resp.execute(command(&["HGET", "t1", "k3"]));
+        assert_eq!(res, RespFrame::Bulk(None));
+
+        let res = resp.execute(command(&["HMGET", "t1", "k2", "k3"]));
+        assert_eq!(
+            res,
+            RespFrame::Array(Some(vec![
+                RespFrame::Bulk(Some("v2".into())),
+                RespFrame::Bulk(None)
+            ]))
+        );
+
+        let res = resp.execute(command(&["HEXISTS", "t1", "k2"]));
+        assert_eq!(res, RespFrame::Integer(1));
+        let res = resp.execute(command(&["HDEL", "t1", "k2", "k3"]));
+        assert_eq!(res, RespFrame::Integer(1));
+        let res = resp.execute(command(&["HEXISTS", "t1", "k2"]));
+        assert_eq!(res, RespFrame::Integer(0));
+
+        let res = resp.execute(command(&["HGETALL", "t1"]));
+        assert_eq!(
+            res,
+            RespFrame::Array(Some(vec![
+                RespFrame::Bulk(Some("k1".into())),
+                RespFrame::Bulk(Some("v11".into()))
+            ]))
+        );
+
+        let res = resp.execute(command(&["PING"]));
+        assert_eq!(res, RespFrame::Simple("PONG".into()));
+        let res = resp.execute(command(&["GET", "k1"]));
+        assert!(matches!(res, RespFrame::Error(e) if e.starts_with("ERR")));
+    }
+
+    #[test]
+    fn resp_service_should_respect_limiter() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        let resp = RespService::new(service);
+        let limiter = RateLimiter::new(LimitConfig {
+            per_ip: Some(Quota::new(1.0, 1)),
+            ..Default::default()
+        });
+        let conn = limiter.connection("127.0.0.1".parse().unwrap());
+
+        let res = resp.execute_limited(command(&["HGET", "t1", "k1"]), &conn);
+        assert_eq!(res, RespFrame::Bulk(None));
+        let res = resp.execute_limited(command(&["HGET", "t1", "k1"]), &conn);
+        assert!(matches!(res, RespFrame::Error(e) if e.starts_with("QUOTA")));
+    }
+
+    #[test]
+    fn resp_reply_should_be_mapped_from_kv_error() {
+        let not_found = || KvError::NotFound("t1".into(), "k1".into());
+        assert_eq!(
+            ReplyKind::Value.reply(not_found().into()),
+            RespFrame::Bulk(None)
+        );
+        assert_eq!(
+            ReplyKind::Values.reply(not_found().into()),
+            RespFrame::from(not_found())
+        );
+
+        let denied = || KvError::PermissionDenied("table t1".into());
+        let res = ReplyKind::Pairs.reply(denied().into());
+        assert_eq!(res, RespFrame::from(denied()));
+        assert!(matches!(res, RespFrame::Error(e) if e.starts_with("NOPERM")));
+
+        let res = RespFrame::from(KvError::ConvertError(1.into(), "Binary".into()));
+        assert!(matches!(res, RespFrame::Error(e) if e.starts_with("WRONGTYPE")));
+    }
+
+    fn command(args: &[&'static str]) -> RespFrame {
+        RespFrame::Array(Some(
+            args.iter()
+                .map(|s| RespFrame::Bulk(Some(Bytes::from_static(s.as_bytes()))))
+                .collect(),
+        ))
+    }
+}
@@ -0,0 +1,404 @@
+use crate::*;
+use prost::Message;
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Component, Path, PathBuf},
+};
+
+impl CommandService for Dump {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        let tables = match self.table.as_str() {
+            // 保留 table 中是 Service 内部的数据，不导出
+            "" => store
+                .get_tables()
+                .map(|tables| tables.into_iter().filter(|t| !is_reserved(t)).collect()),
+            table => Ok(vec![table.to_string()]),
+        };
+
+        let result = tables.and_then(|tables| {
+            let file = File::create(&self.path).map_err(|e| backup_error("dump", &self.path, e))?;
+            let mut writer = BufWriter::new(file);
+            let mut count = 0;
+            for table in tables {
+                // 一边遍历 table 一边写入文件，不需要把整个 table 读到内存里
+                for pair in store.get_iter(&table)? {
+                    let record = DumpRecord {
+                        table: table.clone(),
+                        pair: Some(pair),
+                    };
+                    write_record(&mut writer, &record, self.format())
+                        .map_err(|e| backup_error("dump", &self.path, e))?;
+                    count += 1;
+                }
+            }
+            writer
+                .flush()
+                .map_err(|e| backup_error("dump", &self.path, e))?;
+            Ok(count)
+        });
+
+        match result {
+            Ok(count) => Value::from(count).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Restore {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        // 先完整读一遍文件，文件有错误时不修改任何数据，两遍都是边读边处理
+        let result = read_records(&self.path, self.format())
+            .and_then(|mut records| records.try_for_each(|r| r.map(|_| ())));
+        if let Err(e) = result {
+            return e.into();
+        }
+
+        let result = read_records(&self.path, self.format()).and_then(|records| {
+            let mut cleared = HashSet::new();
+            let mut count = 0;
+            for record in records {
+                let record = record?;
+                // 旧版本导出的文件中可能有保留 table，跳过它们
+                if is_reserved(&record.table) {
+                    continue;
+                }
+                // replace 模式下第一次遇到某个 table 时先清空它
+                if self.mode() == restore::Mode::Replace && cleared.insert(record.table.clone()) {
+                    for pair in store.get_iter(&record.table)? {
+                        store.del(&record.table, &pair.key)?;
+                    }
+                }
+                let pair = record.pair.unwrap_or_default();
+                store.set(&record.table, pair.key, pair.value.unwrap_or_default())?;
+                count += 1;
+            }
+            Ok(count)
+        });
+
+        match result {
+            Ok(count) => Value::from(count).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+fn write_record(
+    writer: &mut impl Write,
+    record: &DumpRecord,
+    format: dump::Format,
+) -> Result<(), String> {
+    match format {
+        dump::Format::Protobuf => writer
+            .write_all(&record.encode_length_delimited_to_vec())
+            .map_err(|e| e.to_string()),
+        dump::Format::Ndjson => {
+            serde_json::to_writer(&mut *writer, record).map_err(|e| e.to_string())?;
+            writer.write_all(b"\n").map_err(|e| e.to_string())
+        }
+    }
+}
+
+fn read_records(
+    path: &str,
+    format: dump::Format,
+) -> Result<Box<dyn Iterator<Item = Result<DumpRecord, KvError>>>, KvError> {
+    let file = File::open(path).map_err(|e| backup_error("restore", path, e))?;
+    let mut reader = BufReader::new(file);
+    let path = path.to_string();
+
+    match format {
+        dump::Format::Protobuf => Ok(Box::new(std::iter::from_fn(move || {
+            match read_delimited(&mut reader) {
+                Ok(Some(data)) => Some(
+                    DumpRecord::decode(&data[..]).map_err(|e| backup_error("restore", &path, e)),
+                ),
+                Ok(None) => None,
+                Err(e) => Some(Err(backup_error("restore", &path, e))),
+            }
+        }))),
+        dump::Format::Ndjson => Ok(Box::new(reader.lines().map(move |line| {
+            let line = line.map_err(|e| backup_error("restore", &path, e))?;
+            serde_json::from_str(&line).map_err(|e| backup_error("restore", &path, e))
+        }))),
+    }
+}
+
+// 读出一个 length delimited 的记录，文件结束时返回 None
+fn read_delimited(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    if reader.fill_buf()?.is_empty() {
+        return Ok(None);
+    }
+
+    // varint 编码的长度，最多 10 个字节
+    let mut len = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        len |= ((byte[0] & 0x7f) as u64) << (7 * i);
+        if byte[0] < 0x80 {
+            // 不按照文件中的长度预先分配内存，避免错误的长度申请过多内存
+            let mut data = Vec::new();
+            reader.take(len).read_to_end(&mut data)?;
+            if data.len() as u64 != len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            return Ok(Some(data));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "invalid record length",
+    ))
+}
+
+/// 把客户端发来的路径解析为备份目录 dir 下的文件，不允许访问 dir 之外的文件
+pub(crate) fn resolve_path(dir: &Path, path: &str) -> Result<PathBuf, KvError> {
+    let denied = || KvError::PermissionDenied(format!("invalid backup path {}", path));
+    // 只允许普通的相对路径，不能是绝对路径，也不能包含 . 和 ..
+    let relative = Path::new(path);
+    if path.is_empty()
+        || !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Err(denied());
+    }
+
+    let dir = dir
+        .canonicalize()
+        .map_err(|e| backup_error("open", &dir.to_string_lossy(), e))?;
+    let full = dir.join(relative);
+    // 文件或者它所在的目录可能是指向 dir 之外的符号链接
+    let resolved = match full.canonicalize() {
+        Ok(resolved) => resolved,
+        Err(_) => {
+            let parent = full.parent().ok_or_else(denied)?;
+            let parent = parent
+                .canonicalize()
+                .map_err(|e| backup_error("open", path, e))?;
+            // 指向不存在的文件的符号链接也无法 canonicalize，写入时会跟着它写到 dir 之外
+            if fs::symlink_metadata(&full).is_ok() {
+                return Err(denied());
+            }
+            parent.join(full.file_name().ok_or_else(denied)?)
+        }
+    };
+    if !resolved.starts_with(&dir) {
+        return Err(denied());
+    }
+
+    Ok(resolved)
+}
+
+fn backup_error(op: &str, path: &str, e: impl ToString) -> KvError {
+    KvError::BackupError(op.into(), path.into(), e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    // 备份目录为 dir 的 Service
+    fn backup_service<Store: Storage>(store: Store, dir: &Path) -> Service<Store> {
+        ServiceInner::new(store).backup_dir(dir).into()
+    }
+
+    fn hset<Store: Storage>(service: &Service<Store>, table: &str, pairs: Vec<(&str, &str)>) {
+        for (k, v) in pairs {
+            service.execute(CommandRequest::new_hset(table, k, v.into()));
+        }
+    }
+
+    #[test]
+    fn dump_and_restore_should_work() {
+        for format in [dump::Format::Protobuf, dump::Format::Ndjson] {
+            let dir = tempdir().unwrap();
+            let service = backup_service(MemTable::new(), dir.path());
+            hset(&service, "t1", vec![("k1", "v1"), ("k2", "v2")]);
+            hset(&service, "t2", vec![("k1", "v1")]);
+            let res = service.execute(CommandRequest::new_dump("", "dump", format));
+            assert_res_ok(res, &[3.into()], &[]);
+
+            // MemTable 导出的数据可以导入 SledDb
+            let service = backup_service(SledDb::new(dir.path().join("db")), dir.path());
+            let cmd = CommandRequest::new_restore("dump", format, restore::Mode::Merge);
+            assert_res_ok(service.execute(cmd), &[3.into()], &[]);
+            let res = service.execute(CommandRequest::new_hget("t1", "k2"));
+            assert_res_ok(res, &["v2".into()], &[]);
+            let res = service.execute(CommandRequest::new_hget("t2", "k1"));
+            assert_res_ok(res, &["v1".into()], &[]);
+        }
+    }
+
+    #[test]
+    fn dump_single_table_should_work() {
+        let dir = tempdir().unwrap();
+        let service = backup_service(MemTable::new(), dir.path());
+        hset(&service, "t1", vec![("k1", "v1"), ("k2", "v2")]);
+        hset(&service, "t2", vec![("k1", "v1")]);
+        let cmd = CommandRequest::new_dump("t2", "dump", dump::Format::Ndjson);
+        assert_res_ok(service.execute(cmd), &[1.into()], &[]);
+    }
+
+    #[test]
+    fn restore_with_replace_mode_should_work() {
+        let dir = tempdir().unwrap();
+        let service = backup_service(MemTable::new(), dir.path());
+        hset(&service, "t1", vec![("k1", "v1")]);
+        let cmd = CommandRequest::new_dump("t1", "dump", dump::Format::Protobuf);
+        assert_res_ok(service.execute(cmd), &[1.into()], &[]);
+
+        // merge 模式会保留已有的 key
+        hset(&service, "t1", vec![("k1", "v11"), ("k2", "v2")]);
+        let cmd = CommandRequest::new_restore("dump", dump::Format::Protobuf, restore::Mode::Merge);
+        service.execute(cmd);
+        let res = service.execute(CommandRequest::new_hmget(
+            "t1",
+            vec!["k1".into(), "k2".into()],
+        ));
+        assert_res_ok(res, &["v1".into(), "v2".into()], &[]);
+
+        // replace 模式会先清空 table
+        let cmd =
+            CommandRequest::new_restore("dump", dump::Format::Protobuf, restore::Mode::Replace);
+        service.execute(cmd);
+        let res = service.execute(CommandRequest::new_hmget(
+            "t1",
+            vec!["k1".into(), "k2".into()],
+        ));
+        assert_res_ok(res, &["v1".into(), Value::default()], &[]);
+    }
+
+    #[test]
+    fn restore_from_missing_file_should_fail() {
+        let dir = tempdir().unwrap();
+        let service = backup_service(MemTable::new(), dir.path());
+        let cmd =
+            CommandRequest::new_restore("missing", dump::Format::Ndjson, restore::Mode::Merge);
+        let res = service.execute(cmd);
+        assert_eq!(res.status, 500);
+        assert!(res.message.contains("missing"));
+    }
+
+    #[test]
+    fn restore_invalid_file_should_not_modify_data() {
+        let dir = tempdir().unwrap();
+        let record = DumpRecord {
+            table: "t1".into(),
+            pair: Some(Kvpair::new("k2", "v2".into())),
+        };
+        let data = format!("{}\nnot json\n", serde_json::to_string(&record).unwrap());
+        fs::write(dir.path().join("dump"), data).unwrap();
+        // 最后一条记录不完整的 protobuf 文件
+        let mut data = record.encode_length_delimited_to_vec();
+        data.extend_from_slice(&[10, 1]);
+        fs::write(dir.path().join("dump.pb"), data).unwrap();
+
+        let service = backup_service(MemTable::new(), dir.path());
+        hset(&service, "t1", vec![("k1", "v1")]);
+        for (path, format) in [
+            ("dump", dump::Format::Ndjson),
+            ("dump.pb", dump::Format::Protobuf),
+        ] {
+            let cmd = CommandRequest::new_restore(path, format, restore::Mode::Replace);
+            assert_eq!(service.execute(cmd).status, 500);
+            let res = service.execute(CommandRequest::new_hmget(
+                "t1",
+                vec!["k1".into(), "k2".into()],
+            ));
+            assert_res_ok(res, &["v1".into(), Value::default()], &[]);
+        }
+    }
+
+    #[test]
+    fn dump_and_restore_should_not_be_dispatched_directly() {
+        let store = MemTable::new();
+        let cmd = CommandRequest::new_dump("", "/tmp/dump", dump::Format::Ndjson);
+        assert_eq!(dispatch(cmd, &store).status, 400);
+        let cmd =
+            CommandRequest::new_restore("/tmp/dump", dump::Format::Ndjson, restore::Mode::Merge);
+        assert_eq!(dispatch(cmd, &store).status, 400);
+    }
+
+    #[test]
+    fn reserved_tables_should_not_be_dumped_or_restored() {
+        let dir = tempdir().unwrap();
+        let service: Service = ServiceInner::new(MemTable::new())
+            .backup_dir(dir.path())
+            .into();
+        service.execute(CommandRequest::new_create_index("t1"));
+        service.execute(CommandRequest::new_hset("t1", "k1", "v1".into()));
+        let res = service.execute(CommandRequest::new_dump("", "dump", dump::Format::Ndjson));
+        assert_res_ok(res, &[1.into()], &[]);
+
+        // 文件中的保留 table 会被跳过
+        let records: Vec<_> = [("t1", "k1"), (INDEX_TABLE, "t1")]
+            .iter()
+            .map(|(table, key)| {
+                let record = DumpRecord {
+                    table: table.to_string(),
+                    pair: Some(Kvpair::new(*key, true.into())),
+                };
+                serde_json::to_string(&record).unwrap()
+            })
+            .collect();
+        fs::write(dir.path().join("dump"), records.join("\n")).unwrap();
+        let store = MemTable::new();
+        let service = backup_service(store.clone(), dir.path());
+        let cmd = CommandRequest::new_restore("dump", dump::Format::Ndjson, restore::Mode::Merge);
+        assert_res_ok(service.execute(cmd), &[1.into()], &[]);
+        assert_eq!(store.get(INDEX_TABLE, "t1"), Ok(None));
+    }
+
+    #[test]
+    fn backup_path_should_be_confined_to_backup_dir() {
+        let dir = tempdir().unwrap();
+        let service: Service = ServiceInner::new(MemTable::new())
+            .backup_dir(dir.path())
+            .into();
+        service.execute(CommandRequest::new_hset("t1", "k1", "v1".into()));
+
+        let dump = |path: &str| CommandRequest::new_dump("", path, dump::Format::Protobuf);
+        let restore = |path: &str| {
+            CommandRequest::new_restore(path, dump::Format::Protobuf, restore::Mode::Merge)
+        };
+        assert_res_ok(service.execute(dump("dump")), &[1.into()], &[]);
+        assert!(dir.path().join("dump").exists());
+        assert_res_ok(service.execute(restore("dump")), &[1.into()], &[]);
+
+        let outside = tempdir().unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("link")).unwrap();
+        let escaped = outside.path().join("dump").to_string_lossy().to_string();
+        for path in [
+            "",
+            "/etc/passwd",
+            &escaped,
+            "../dump",
+            "a/../../dump",
+            "./dump",
+        ] {
+            assert_eq!(service.execute(dump(path)).status, 403, "{}", path);
+            assert_eq!(service.execute(restore(path)).status, 403, "{}", path);
+        }
+        #[cfg(unix)]
+        {
+            assert_eq!(service.execute(dump("link/dump")).status, 403);
+            assert!(!outside.path().join("dump").exists());
+
+            // 指向 dir 之外不存在的文件的符号链接
+            let target = outside.path().join("dangling");
+            std::os::unix::fs::symlink(&target, dir.path().join("dangling")).unwrap();
+            assert_eq!(service.execute(dump("dangling")).status, 403);
+            assert!(!target.exists());
+        }
+
+        // 没有设置备份目录时不允许 Dump/Restore
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        assert_eq!(service.execute(dump("dump")).status, 403);
+        assert_eq!(service.execute(restore("dump")).status, 403);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{command_request::RequestData, CommandRequest, KvError, Kvpair, Storage, Value};
+use std::cell::RefCell;
+
+/// 命令执行时 table 中一个 key 发生的变更
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Change {
+    pub table: String,
+    pub key: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// 会修改数据的命令所修改的 table
+pub(crate) fn write_table(cmd: &CommandRequest) -> Option<&str> {
+    match cmd.request_data {
+        Some(RequestData::Hset(ref v)) => Some(&v.table),
+        Some(RequestData::Hmset(ref v)) => Some(&v.table),
+        Some(RequestData::Hdel(ref v)) => Some(&v.table),
+        Some(RequestData::Hmdel(ref v)) => Some(&v.table),
+        _ => None,
+    }
+}
+
+/// 包装一个 Storage，记录通过它完成的所有修改
+pub(crate) struct Recorder<'a, S> {
+    store: &'a S,
+    changes: RefCell<Vec<Change>>,
+}
+
+impl<'a, S: Storage> Recorder<'a, S> {
+    pub fn new(store: &'a S) -> Self {
+        Self {
+            store,
+            changes: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn into_changes(self) -> Vec<Change> {
+        self.changes.into_inner()
+    }
+}
+
+impl<'a, S: Storage> Storage for Recorder<'a, S> {
+    fn get(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        self.store.get(table, key)
+    }
+
+    fn set(
+        &self,
+        table: &str,
+        key: impl Into<String>,
+        value: impl Into<Value>,
+    ) -> Result<Option<Value>, KvError> {
+        let key = key.into();
+        let value = value.into();
+        let old = self.store.set(table, key.clone(), value.clone())?;
+        self.changes.borrow_mut().push(Change {
+            table: table.into(),
+            key,
+            old: old.clone(),
+            new: Some(value),
+        });
+        Ok(old)
+    }
+
+    fn contains(&self, table: &str, key: &str) -> Result<bool, KvError> {
+        self.store.contains(table, key)
+    }
+
+    fn del(&self, table: &str, key: &str) -> Result<Option<Value>, KvError> {
+        let old = self.store.del(table, key)?;
+        // 删除不存在的 key 没有修改任何数据
+        if old.is_some() {
+            self.changes.borrow_mut().push(Change {
+                table: table.into(),
+                key: key.into(),
+                old: old.clone(),
+                new: None,
+            });
+        }
+        Ok(old)
+    }
+
+    fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError> {
+        self.store.get_all(table)
+    }
+
+    fn get_iter(&self, table: &str) -> Result<Box<dyn Iterator<Item = Kvpair>>, KvError> {
+        self.store.get_iter(table)
+    }
+
+    fn get_tables(&self) -> Result<Vec<String>, KvError> {
+        self.store.get_tables()
+    }
+}
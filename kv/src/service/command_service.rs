@@ -10,6 +10,20 @@ impl CommandService for Hget {
     }
 }
 
+impl CommandService for Hmget {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        self.keys
+            .iter()
+            .map(|key| match store.get(&self.table, key) {
+                Ok(Some(v)) => Ok(v),
+                Ok(None) => Ok(Value::default()),
+                Err(e) => Err(e),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .into()
+    }
+}
+
 impl CommandService for Hgetall {
     fn execute(self, store: &impl Storage) -> CommandResponse {
         match store.get_all(&self.table) {
@@ -32,10 +46,69 @@ impl CommandService for Hset {
     }
 }
 
+impl CommandService for Hdel {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.del(&self.table, &self.key) {
+            Ok(Some(v)) => v.into(),
+            Ok(None) => Value::default().into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Hmset {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        self.pairs
+            .into_iter()
+            .map(
+                |pair| match store.set(&self.table, pair.key, pair.value.unwrap_or_default()) {
+                    Ok(Some(v)) => Ok(v),
+                    Ok(None) => Ok(Value::default()),
+                    Err(e) => Err(e),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()
+            .into()
+    }
+}
+
+impl CommandService for Hmdel {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        self.keys
+            .iter()
+            .map(|key| match store.del(&self.table, key) {
+                Ok(Some(v)) => Ok(v),
+                Ok(None) => Ok(Value::default()),
+                Err(e) => Err(e),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .into()
+    }
+}
+
+impl CommandService for Hexist {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        match store.contains(&self.table, &self.key) {
+            Ok(v) => Value::from(v).into(),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl CommandService for Hmexist {
+    fn execute(self, store: &impl Storage) -> CommandResponse {
+        self.keys
+            .iter()
+            .map(|key| store.contains(&self.table, key).map(Value::from))
+            .collect::<Result<Vec<_>, _>>()
+            .into()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::command_request::RequestData;
+    use crate::{dispatch, set_key_pairs};
 
     #[test]
     fn haset_should_worl() {
@@ -48,14 +121,66 @@ mod tests {
         assert_res_ok(res, &["world".into()], &[]);
     }
 
-    // 从Request 中获取 Response, 目前处理HGET/HGETALL/HSET
-    fn dispatch(cmd: CommandRequest, store: &impl Storage) -> CommandResponse {
-        match cmd.request_data.unwrap() {
-            RequestData::Hget(v) => v.execute(store),
-            RequestData::Hgetall(v) => v.execute(store),
-            RequestData::Hset(v) => v.execute(store),
-            _ => todo!(),
-        }
+    #[test]
+    fn hdel_should_work() {
+        let store = MemTable::new();
+        let cmd = CommandRequest::new_hset("t1", "hello", "world".into());
+        dispatch(cmd, &store);
+
+        let cmd = CommandRequest::new_hdel("t1", "hello");
+        let res = dispatch(cmd.clone(), &store);
+        assert_res_ok(res, &["world".into()], &[]);
+
+        // 再次删除返回空值
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[Value::default()], &[]);
+    }
+
+    #[test]
+    fn hmget_should_work() {
+        let store = MemTable::new();
+        set_key_pairs("t1", vec![("k1", "v1"), ("k2", "v2")], &store);
+
+        let cmd = CommandRequest::new_hmget("t1", vec!["k1".into(), "k3".into()]);
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &["v1".into(), Value::default()], &[]);
+    }
+
+    #[test]
+    fn hmset_should_work() {
+        let store = MemTable::new();
+        set_key_pairs("t1", vec![("k1", "v1")], &store);
+
+        let pairs = vec![
+            Kvpair::new("k1", "v11".into()),
+            Kvpair::new("k2", "v2".into()),
+        ];
+        let cmd = CommandRequest::new_hmset("t1", pairs);
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &["v1".into(), Value::default()], &[]);
+    }
+
+    #[test]
+    fn hmdel_should_work() {
+        let store = MemTable::new();
+        set_key_pairs("t1", vec![("k1", "v1"), ("k2", "v2")], &store);
+
+        let cmd = CommandRequest::new_hmdel("t1", vec!["k1".into(), "k3".into()]);
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &["v1".into(), Value::default()], &[]);
+    }
+
+    #[test]
+    fn hexist_should_work() {
+        let store = MemTable::new();
+        set_key_pairs("t1", vec![("k1", "v1")], &store);
+
+        let res = dispatch(CommandRequest::new_hexist("t1", "k1"), &store);
+        assert_res_ok(res, &[true.into()], &[]);
+
+        let cmd = CommandRequest::new_hmexist("t1", vec!["k1".into(), "k2".into()]);
+        let res = dispatch(cmd, &store);
+        assert_res_ok(res, &[true.into(), false.into()], &[]);
     }
 
     fn assert_res_ok(mut res: CommandResponse, values: &[Value], pairs: &[Kvpair]) {
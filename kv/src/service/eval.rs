@@ -0,0 +1,304 @@
+use crate::{value, CommandResponse, Eval, KvError, Storage, Value};
+use rhai::{
+    module_resolvers::DummyModuleResolver, Array, Blob, Dynamic, Engine, EvalAltResult,
+    ImmutableString, Scope, FLOAT, INT,
+};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    rc::Rc,
+};
+
+/// 脚本默认最多执行的操作数
+pub const DEFAULT_EVAL_MAX_OPERATIONS: u64 = 100_000;
+
+impl Eval {
+    /// 执行脚本，调用者需要保证执行期间 keys 所在的 table 不会被修改
+    pub(crate) fn run(self, store: &impl Storage, max_operations: u64) -> CommandResponse {
+        self.try_run(store, max_operations).into()
+    }
+
+    fn try_run(self, store: &impl Storage, max_operations: u64) -> Result<Vec<Value>, KvError> {
+        let mut values = HashMap::new();
+        for key in self.keys {
+            let value = store.get(&key.table, &key.key)?;
+            values.insert((key.table, key.key), value);
+        }
+
+        let sandbox = Rc::new(RefCell::new(Sandbox {
+            values,
+            written: BTreeSet::new(),
+            error: None,
+        }));
+        let engine = new_engine(&sandbox, max_operations);
+        let mut scope = Scope::new();
+        let args: Array = self.args.into_iter().map(to_dynamic).collect();
+        scope.push_constant("ARGS", args);
+        let result = engine.eval_with_scope::<Dynamic>(&mut scope, &self.script);
+
+        let mut sandbox = sandbox.borrow_mut();
+        let result = match result {
+            Ok(v) => v,
+            Err(e) => {
+                return Err(sandbox
+                    .take_error(&e)
+                    .unwrap_or_else(|| KvError::ScriptError(e.to_string())))
+            }
+        };
+        let result = match result.is::<Array>() {
+            true => result
+                .cast::<Array>()
+                .into_iter()
+                .map(to_value)
+                .collect::<Result<Vec<_>, _>>()?,
+            false => vec![to_value(result)?],
+        };
+
+        // 脚本成功执行之后才写入 store，出错时不会留下部分修改
+        for (table, key) in &sandbox.written {
+            match sandbox.values[&(table.clone(), key.clone())] {
+                Some(ref value) => store.set(table, key.clone(), value.clone())?,
+                None => store.del(table, key)?,
+            };
+        }
+
+        Ok(result)
+    }
+}
+
+// 脚本能访问的数据，修改只记录在这里
+struct Sandbox {
+    values: HashMap<(String, String), Option<Value>>,
+    written: BTreeSet<(String, String)>,
+    error: Option<KvError>,
+}
+
+impl Sandbox {
+    fn entry(&mut self, table: &str, key: &str) -> Result<&mut Option<Value>, Box<EvalAltResult>> {
+        let k = (table.to_string(), key.to_string());
+        if !self.values.contains_key(&k) {
+            let e = KvError::PermissionDenied(format!(
+                "key {} of table {} is not declared in keys",
+                key, table
+            ));
+            let msg = e.to_string();
+            self.error = Some(e);
+            return Err(msg.into());
+        }
+        Ok(self.values.get_mut(&k).unwrap())
+    }
+
+    // 脚本可能用 try/catch 捕获 sandbox 的错误，只有脚本最终的错误就是这个错误时才返回它
+    fn take_error(&mut self, e: &EvalAltResult) -> Option<KvError> {
+        let error = self.error.take()?;
+        match e.unwrap_inner() {
+            EvalAltResult::ErrorRuntime(v, _) if v.to_string() == error.to_string() => Some(error),
+            _ => None,
+        }
+    }
+
+    fn get(&mut self, table: &str, key: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+        let value = self.entry(table, key)?.clone();
+        Ok(value.map_or(Dynamic::UNIT, to_dynamic))
+    }
+
+    fn set(
+        &mut self,
+        table: &str,
+        key: &str,
+        value: Dynamic,
+    ) -> Result<Dynamic, Box<EvalAltResult>> {
+        // get 对不存在的 key 返回 ()，对应地 set 一个 () 就是删除
+        if value.is_unit() {
+            return self.del(table, key);
+        }
+        let value = match to_value(value) {
+            Ok(v) => v,
+            Err(e) => return Err(e.to_string().into()),
+        };
+        let old = self.entry(table, key)?.replace(value);
+        self.written.insert((table.into(), key.into()));
+        Ok(old.map_or(Dynamic::UNIT, to_dynamic))
+    }
+
+    fn del(&mut self, table: &str, key: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+        let old = self.entry(table, key)?.take();
+        self.written.insert((table.into(), key.into()));
+        Ok(old.map_or(Dynamic::UNIT, to_dynamic))
+    }
+}
+
+// 创建只能访问 sandbox 的 Engine，并限制脚本使用的资源
+fn new_engine(sandbox: &Rc<RefCell<Sandbox>>, max_operations: u64) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(max_operations)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(1024 * 1024)
+        .set_max_array_size(10_000)
+        .set_max_map_size(10_000)
+        .set_module_resolver(DummyModuleResolver::new())
+        .on_print(|_| {})
+        .on_debug(|_, _, _| {});
+
+    let sb = sandbox.clone();
+    engine.register_fn("get", move |table: &str, key: &str| {
+        sb.borrow_mut().get(table, key)
+    });
+    let sb = sandbox.clone();
+    engine.register_fn("set", move |table: &str, key: &str, value: Dynamic| {
+        sb.borrow_mut().set(table, key, value)
+    });
+    let sb = sandbox.clone();
+    engine.register_fn("del", move |table: &str, key: &str| {
+        sb.borrow_mut().del(table, key)
+    });
+
+    engine
+}
+
+fn to_dynamic(v: Value) -> Dynamic {
+    match v.value {
+        Some(value::Value::String(s)) => s.into(),
+        Some(value::Value::Binary(b)) => Dynamic::from_blob(b.to_vec()),
+        Some(value::Value::Integer(i)) => i.into(),
+        Some(value::Value::Float(f)) => f.into(),
+        Some(value::Value::Bool(b)) => b.into(),
+        // 编码过的结构体在脚本中只能原样传递
+        Some(value::Value::Encoded(_)) => Dynamic::from(v),
+        None => Dynamic::UNIT,
+    }
+}
+
+fn to_value(v: Dynamic) -> Result<Value, KvError> {
+    if v.is_unit() {
+        Ok(Value::default())
+    } else if v.is::<INT>() {
+        Ok(v.cast::<INT>().into())
+    } else if v.is::<FLOAT>() {
+        Ok(v.cast::<FLOAT>().into())
+    } else if v.is::<bool>() {
+        Ok(v.cast::<bool>().into())
+    } else if v.is::<ImmutableString>() {
+        Ok(v.cast::<ImmutableString>().as_str().into())
+    } else if v.is::<Blob>() {
+        Ok(Value {
+            value: Some(value::Value::Binary(v.cast::<Blob>().into())),
+        })
+    } else if v.is::<Value>() {
+        Ok(v.cast::<Value>())
+    } else {
+        Err(KvError::ScriptError(format!(
+            "Cannot convert {} to Value",
+            v.type_name()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        assert_res_ok, CommandRequest, EvalKey, MemTable, Service, ServiceInner, Storage, Value,
+    };
+
+    fn service() -> Service {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        service.execute(CommandRequest::new_hset("accounts", "alice", 100.into()));
+        service.execute(CommandRequest::new_hset("accounts", "bob", 20.into()));
+        service
+    }
+
+    #[test]
+    fn eval_should_read_and_write_declared_keys() {
+        let service = service();
+        let script = r#"
+            let amount = ARGS[0];
+            let from = get("accounts", "alice");
+            if from < amount { throw "insufficient balance"; }
+            set("accounts", "alice", from - amount);
+            set("accounts", "bob", get("accounts", "bob") + amount);
+            set("logs", "last", "alice->bob");
+            [get("accounts", "alice"), get("accounts", "bob")]
+        "#;
+        let keys = vec![
+            EvalKey::new("accounts", "alice"),
+            EvalKey::new("accounts", "bob"),
+            EvalKey::new("logs", "last"),
+        ];
+        let res = service.execute(CommandRequest::new_eval(script, keys, vec![30.into()]));
+        assert_res_ok(res, &[70.into(), 50.into()], &[]);
+
+        let res = service.execute(CommandRequest::new_hget("logs", "last"));
+        assert_res_ok(res, &["alice->bob".into()], &[]);
+    }
+
+    #[test]
+    fn eval_set_unit_should_delete_key() {
+        let service = service();
+        let keys = vec![EvalKey::new("accounts", "bob")];
+        let cmd = CommandRequest::new_eval(r#"set("accounts", "bob", ())"#, keys, vec![]);
+        assert_res_ok(service.execute(cmd), &[20.into()], &[]);
+
+        let res = service.execute(CommandRequest::new_hexist("accounts", "bob"));
+        assert_res_ok(res, &[false.into()], &[]);
+    }
+
+    #[test]
+    fn failed_eval_should_not_write() {
+        let service = service();
+        let script = r#"
+            set("accounts", "alice", 0);
+            throw "oops";
+        "#;
+        let keys = vec![EvalKey::new("accounts", "alice")];
+        let res = service.execute(CommandRequest::new_eval(script, keys, vec![]));
+        assert_eq!(res.status, 400);
+        assert!(res.message.contains("oops"));
+
+        let res = service.execute(CommandRequest::new_hget("accounts", "alice"));
+        assert_res_ok(res, &[100.into()], &[]);
+    }
+
+    #[test]
+    fn eval_should_not_access_undeclared_keys() {
+        let service = service();
+        let keys = vec![EvalKey::new("accounts", "alice")];
+        let cmd = CommandRequest::new_eval(r#"del("accounts", "bob")"#, keys, vec![]);
+        let res = service.execute(cmd);
+        assert_eq!(res.status, 403);
+
+        let res = service.execute(CommandRequest::new_hget("accounts", "bob"));
+        assert_res_ok(res, &[20.into()], &[]);
+
+        // 脚本捕获了 sandbox 的错误之后，之后的错误不应该被当作 sandbox 的错误
+        let script = r#"
+            try { get("accounts", "bob"); } catch {}
+            throw "oops";
+        "#;
+        let keys = vec![EvalKey::new("accounts", "alice")];
+        let res = service.execute(CommandRequest::new_eval(script, keys.clone(), vec![]));
+        assert_eq!(res.status, 400);
+        assert!(res.message.contains("oops"));
+
+        // 在函数中访问未声明的 key 仍然返回 403
+        let script = r#"
+            fn steal() { del("accounts", "bob") }
+            steal()
+        "#;
+        let res = service.execute(CommandRequest::new_eval(script, keys, vec![]));
+        assert_eq!(res.status, 403);
+    }
+
+    #[test]
+    fn eval_should_be_bounded_by_max_operations() {
+        let store = MemTable::new();
+        store.set("t1", "k1", 1).unwrap();
+        let service: Service = ServiceInner::new(store).eval_max_operations(1000).into();
+        let res = service.execute(CommandRequest::new_eval("loop {}", vec![], vec![]));
+        assert_eq!(res.status, 400);
+
+        let res = service.execute(CommandRequest::new_eval("40 + 2", vec![], vec![]));
+        assert_res_ok(res, &[Value::from(42)], &[]);
+    }
+}
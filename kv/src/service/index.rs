@@ -0,0 +1,265 @@
+use super::change::Change;
+use crate::{command_request::RequestData, CommandRequest, KvError, Kvpair, Storage, Value};
+use dashmap::DashMap;
+use prost::Message;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex},
+};
+use tracing::{info, warn};
+
+/// 记录哪些 table 建立了索引，保存在 store 中以便重启后重建索引
+pub const INDEX_TABLE: &str = "__index__";
+
+/// 以 __ 开头的 table 由 Service 内部使用，客户端不能访问，也不会被 Dump 导出
+pub fn is_reserved(table: &str) -> bool {
+    table.starts_with("__")
+}
+
+/// 命令中访问的第一个保留 table
+pub(crate) fn reserved_table(cmd: &CommandRequest) -> Option<&str> {
+    let tables: Vec<&str> = match cmd.request_data {
+        Some(RequestData::Hget(ref v)) => vec![&v.table],
+        Some(RequestData::Hgetall(ref v)) => vec![&v.table],
+        Some(RequestData::Hmget(ref v)) => vec![&v.table],
+        Some(RequestData::Hset(ref v)) => vec![&v.table],
+        Some(RequestData::Hmset(ref v)) => vec![&v.table],
+        Some(RequestData::Hdel(ref v)) => vec![&v.table],
+        Some(RequestData::Hmdel(ref v)) => vec![&v.table],
+        Some(RequestData::Hexist(ref v)) => vec![&v.table],
+        Some(RequestData::Hmexist(ref v)) => vec![&v.table],
+        Some(RequestData::Dump(ref v)) => vec![&v.table],
+        Some(RequestData::CreateIndex(ref v)) => vec![&v.table],
+        Some(RequestData::FindByValue(ref v)) => vec![&v.table],
+        Some(RequestData::Watch(ref v)) => vec![&v.table],
+        Some(RequestData::Eval(ref v)) => v.keys.iter().map(|k| k.table.as_str()).collect(),
+        Some(RequestData::Restore(_)) | None => vec![],
+    };
+    tables.into_iter().find(|t| is_reserved(t))
+}
+
+/// 一个 table 上从 value 到 key 的倒排索引
+#[derive(Debug, Default)]
+struct TableIndex {
+    // Value 没有实现 Hash，使用它的 protobuf 编码作为索引的 key
+    entries: HashMap<Vec<u8>, BTreeSet<String>>,
+}
+
+impl TableIndex {
+    fn build(table: &str, store: &impl Storage) -> Result<Self, KvError> {
+        let mut index = Self::default();
+        for pair in store.get_iter(table)? {
+            index.insert(pair.key, &pair.value.unwrap_or_default());
+        }
+        Ok(index)
+    }
+
+    fn apply(&mut self, change: &Change) {
+        if let Some(ref old) = change.old {
+            self.remove(&change.key, old);
+        }
+        if let Some(ref new) = change.new {
+            self.insert(change.key.clone(), new);
+        }
+    }
+
+    fn insert(&mut self, key: String, value: &Value) {
+        self.entries
+            .entry(value.encode_to_vec())
+            .or_default()
+            .insert(key);
+    }
+
+    fn remove(&mut self, key: &str, value: &Value) {
+        let data = value.encode_to_vec();
+        if let Some(keys) = self.entries.get_mut(&data) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.entries.remove(&data);
+            }
+        }
+    }
+
+    fn find(&self, value: &Value) -> Vec<String> {
+        self.entries
+            .get(&value.encode_to_vec())
+            .map(|keys| keys.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Service 维护的所有索引
+#[derive(Debug, Default)]
+pub(crate) struct Indexes {
+    tables: DashMap<String, Arc<Mutex<TableIndex>>>,
+}
+
+impl Indexes {
+    /// 根据 store 中记录的索引定义重建所有索引
+    pub fn load(store: &impl Storage) -> Self {
+        let indexes = Self::default();
+        if let Err(e) = indexes.reload(store) {
+            warn!("Failed to load indexes: {}", e);
+        }
+        indexes
+    }
+
+    /// 丢弃内存中的索引，根据 store 中的数据重建
+    pub fn reload(&self, store: &impl Storage) -> Result<(), KvError> {
+        self.tables.clear();
+        for pair in store.get_all(INDEX_TABLE)? {
+            self.build(&pair.key, store)?;
+        }
+        Ok(())
+    }
+
+    fn get(&self, table: &str) -> Option<Arc<Mutex<TableIndex>>> {
+        self.tables.get(table).map(|v| v.value().clone())
+    }
+
+    /// 根据命令带来的变更更新对应 table 的索引
+    pub fn apply(&self, changes: &[Change]) {
+        for change in changes {
+            if let Some(index) = self.get(&change.table) {
+                index.lock().unwrap().apply(change);
+            }
+        }
+    }
+
+    /// 为 table 创建索引，调用者需要保证创建期间 table 不会被修改
+    pub fn create(&self, table: &str, store: &impl Storage) -> Result<(), KvError> {
+        if is_reserved(table) {
+            return Err(KvError::InvalidCommand(format!(
+                "Cannot create index on {}",
+                table
+            )));
+        }
+        store.set(INDEX_TABLE, table, true)?;
+        self.build(table, store)
+    }
+
+    pub fn find(&self, table: &str, value: &Value) -> Result<Vec<Kvpair>, KvError> {
+        let index = self
+            .get(table)
+            .ok_or_else(|| KvError::InvalidCommand(format!("No index on table {}", table)))?;
+        let keys = index.lock().unwrap().find(value);
+        Ok(keys
+            .into_iter()
+            .map(|key| Kvpair::new(key, value.clone()))
+            .collect())
+    }
+
+    fn build(&self, table: &str, store: &impl Storage) -> Result<(), KvError> {
+        let index = TableIndex::build(table, store)?;
+        info!(
+            "Built index for table {}: {} values",
+            table,
+            index.entries.len()
+        );
+        self.tables
+            .insert(table.into(), Arc::new(Mutex::new(index)));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assert_res_ok, EvalKey, MemTable, Service, ServiceInner, SledDb, Watch};
+    use std::{thread, time::Duration};
+    use tempfile::tempdir;
+
+    #[test]
+    fn index_should_follow_writes() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        service.execute(CommandRequest::new_hset("users", "u1", "admin".into()));
+        let res = service.execute(CommandRequest::new_create_index("users"));
+        assert_res_ok(res, &[Value::default()], &[]);
+
+        service.execute(CommandRequest::new_hset("users", "u2", "guest".into()));
+        service.execute(CommandRequest::new_hset("users", "u3", "admin".into()));
+        let res = service.execute(CommandRequest::new_find_by_value("users", "admin".into()));
+        let pairs = [
+            Kvpair::new("u1", "admin".into()),
+            Kvpair::new("u3", "admin".into()),
+        ];
+        assert_res_ok(res, &[], &pairs);
+
+        // 修改和删除 key 之后索引同步更新
+        service.execute(CommandRequest::new_hset("users", "u1", "guest".into()));
+        service.execute(CommandRequest::new_hdel("users", "u2"));
+        let res = service.execute(CommandRequest::new_find_by_value("users", "guest".into()));
+        assert_res_ok(res, &[], &[Kvpair::new("u1", "guest".into())]);
+        let res = service.execute(CommandRequest::new_find_by_value("users", "admin".into()));
+        assert_res_ok(res, &[], &[Kvpair::new("u3", "admin".into())]);
+    }
+
+    #[test]
+    fn find_without_index_should_fail() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        let res = service.execute(CommandRequest::new_find_by_value("users", "admin".into()));
+        assert_eq!(res.status, 400);
+        assert!(res.message.contains("No index"));
+    }
+
+    #[test]
+    fn index_should_be_rebuilt_for_sleddb() {
+        let dir = tempdir().unwrap();
+        let service: Service<SledDb> = ServiceInner::new(SledDb::new(&dir)).into();
+        service.execute(CommandRequest::new_create_index("users"));
+        service.execute(CommandRequest::new_hset("users", "u1", "admin".into()));
+
+        drop(service);
+
+        // 重新打开同一个目录，根据 store 中的数据重建索引
+        // sled 的后台线程退出之后才会释放目录的锁，重试到可以打开为止
+        let store = (0..100)
+            .find_map(|_| {
+                let store = SledDb::try_new(&dir);
+                if store.is_err() {
+                    thread::sleep(Duration::from_millis(50));
+                }
+                store.ok()
+            })
+            .unwrap();
+        let service: Service<SledDb> = ServiceInner::new(store).into();
+        let res = service.execute(CommandRequest::new_find_by_value("users", "admin".into()));
+        assert_res_ok(res, &[], &[Kvpair::new("u1", "admin".into())]);
+    }
+
+    #[test]
+    fn reserved_table_should_be_rejected() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        service.execute(CommandRequest::new_create_index("users"));
+
+        let key = |table: &str| EvalKey {
+            table: table.into(),
+            key: "k1".into(),
+        };
+        for cmd in [
+            CommandRequest::new_hset(INDEX_TABLE, "users", false.into()),
+            CommandRequest::new_hdel(INDEX_TABLE, "users"),
+            CommandRequest::new_hgetall(INDEX_TABLE),
+            CommandRequest::new_create_index("__users"),
+            CommandRequest::new_find_by_value(INDEX_TABLE, true.into()),
+            CommandRequest::new_watch(INDEX_TABLE, "", 0),
+            CommandRequest::new_eval("1", vec![key("t1"), key(INDEX_TABLE)], vec![]),
+        ] {
+            let res = service.execute(cmd);
+            assert_eq!(res.status, 403);
+            assert!(res.message.contains("reserved"));
+        }
+        assert!(service.watch(watch(INDEX_TABLE)).is_err());
+
+        // 索引定义没有被修改
+        let res = service.execute(CommandRequest::new_find_by_value("users", "admin".into()));
+        assert_res_ok(res, &[], &[]);
+    }
+
+    fn watch(table: &str) -> Watch {
+        Watch {
+            table: table.into(),
+            ..Default::default()
+        }
+    }
+}
@@ -1,10 +1,28 @@
 use crate::{
     command_request::RequestData, CommandRequest, CommandResponse, KvError, MemTable, Storage,
+    Value, Watch,
+};
+use change::{Change, Recorder};
+use dashmap::DashMap;
+use index::Indexes;
+use std::{
+    collections::BTreeSet,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
 };
-use std::sync::Arc;
 use tracing::debug;
+use watch::ChangeLog;
 
+mod backup;
+mod change;
 mod command_service;
+mod eval;
+mod index;
+mod watch;
+
+pub use eval::DEFAULT_EVAL_MAX_OPERATIONS;
+pub use index::{is_reserved, INDEX_TABLE};
+pub use watch::{Subscription, DEFAULT_CHANGE_LOG_CAPACITY};
 
 pub trait CommandService {
     /// 处理Command, 返回Response
@@ -25,6 +43,15 @@ impl<Store> Clone for Service<Store> {
 
 pub struct ServiceInner<Store> {
     store: Store,
+    // Dump/Restore/CreateIndex 独占，其它命令共享，保证执行期间数据不会被修改
+    lock: RwLock<()>,
+    // 同一个 table 上的写入串行执行，保证索引和修改事件的顺序与写入一致
+    table_locks: DashMap<String, Arc<Mutex<()>>>,
+    indexes: Indexes,
+    changes: ChangeLog,
+    eval_max_operations: u64,
+    // Dump/Restore 只能访问这个目录下的文件，没有设置时不允许 Dump/Restore
+    backup_dir: Option<PathBuf>,
     on_received: Vec<fn(&CommandRequest)>,
     on_executed: Vec<fn(&CommandResponse)>,
     on_before_send: Vec<fn(&mut CommandResponse)>,
@@ -60,7 +87,13 @@ impl<Arg> NotifyMut<Arg> for Vec<fn(&mut Arg)> {
 impl<Store: Storage> ServiceInner<Store> {
     pub fn new(store: Store) -> Self {
         Self {
+            indexes: Indexes::load(&store),
             store,
+            lock: RwLock::new(()),
+            table_locks: DashMap::new(),
+            changes: ChangeLog::new(DEFAULT_CHANGE_LOG_CAPACITY),
+            eval_max_operations: DEFAULT_EVAL_MAX_OPERATIONS,
+            backup_dir: None,
             on_received: Vec::new(),
             on_executed: Vec::new(),
             on_before_send: Vec::new(),
@@ -68,6 +101,25 @@ impl<Store: Storage> ServiceInner<Store> {
         }
     }
 
+    /// 设置保留的修改事件数量，更早的事件无法再通过 Watch 获取，capacity 必须大于 0
+    pub fn change_log_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "change log capacity must be greater than 0");
+        self.changes = ChangeLog::new(capacity);
+        self
+    }
+
+    /// 设置 Eval 脚本最多执行的操作数
+    pub fn eval_max_operations(mut self, max_operations: u64) -> Self {
+        self.eval_max_operations = max_operations;
+        self
+    }
+
+    /// 设置 Dump/Restore 使用的备份目录，客户端只能使用这个目录下的相对路径
+    pub fn backup_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.backup_dir = Some(dir.into());
+        self
+    }
+
     pub fn fn_received(mut self, f: fn(&CommandRequest)) -> Self {
         self.on_received.push(f);
         self
@@ -87,6 +139,119 @@ impl<Store: Storage> ServiceInner<Store> {
         self.on_after_send.push(f);
         self
     }
+
+    // 处理需要用到 Service 中的锁和索引的命令，其它命令交给 dispatch
+    fn dispatch(&self, cmd: CommandRequest) -> CommandResponse {
+        if let Some(table) = index::reserved_table(&cmd) {
+            return KvError::PermissionDenied(format!("table {} is reserved", table)).into();
+        }
+
+        match cmd.request_data {
+            Some(RequestData::Dump(mut param)) => {
+                param.path = match self.backup_path(&param.path) {
+                    Ok(path) => path,
+                    Err(e) => return e.into(),
+                };
+                let _guard = self.lock.write().unwrap();
+                param.execute(&self.store)
+            }
+            Some(RequestData::Restore(mut param)) => {
+                param.path = match self.backup_path(&param.path) {
+                    Ok(path) => path,
+                    Err(e) => return e.into(),
+                };
+                let _guard = self.lock.write().unwrap();
+                let recorder = Recorder::new(&self.store);
+                let res = param.execute(&recorder);
+                self.changes.append(recorder.into_changes());
+                // 导入的数据没有更新索引，需要重建
+                match self.indexes.reload(&self.store) {
+                    Ok(()) => res,
+                    Err(e) => e.into(),
+                }
+            }
+            Some(RequestData::CreateIndex(param)) => {
+                let _guard = self.lock.write().unwrap();
+                match self.indexes.create(&param.table, &self.store) {
+                    Ok(()) => Value::default().into(),
+                    Err(e) => e.into(),
+                }
+            }
+            Some(RequestData::FindByValue(param)) => {
+                let _guard = self.lock.read().unwrap();
+                let value = param.value.unwrap_or_default();
+                // 和写入同一个 table 的命令互斥，不会读到写入了 store 但还没有更新的索引
+                self.with_table_locks([param.table.clone()].into(), || {
+                    match self.indexes.find(&param.table, &value) {
+                        Ok(pairs) => pairs.into(),
+                        Err(e) => e.into(),
+                    }
+                })
+            }
+            Some(RequestData::Watch(param)) => match self.changes.since(&param) {
+                Ok(events) => events.into(),
+                Err(e) => e.into(),
+            },
+            Some(RequestData::Eval(param)) => {
+                let _guard = self.lock.read().unwrap();
+                let tables: BTreeSet<_> = param.keys.iter().map(|k| k.table.clone()).collect();
+                self.with_table_locks(tables, || {
+                    let recorder = Recorder::new(&self.store);
+                    let res = param.run(&recorder, self.eval_max_operations);
+                    self.commit(recorder.into_changes());
+                    res
+                })
+            }
+            _ => {
+                let _guard = self.lock.read().unwrap();
+                let table = match change::write_table(&cmd) {
+                    Some(table) => table.to_string(),
+                    None => return dispatch(cmd, &self.store),
+                };
+
+                // 持有 table 的锁直到提交完变更，保证写入、更新索引和记录事件是原子的
+                self.with_table_locks([table].into(), || {
+                    let recorder = Recorder::new(&self.store);
+                    let res = dispatch(cmd, &recorder);
+                    self.commit(recorder.into_changes());
+                    res
+                })
+            }
+        }
+    }
+
+    fn backup_path(&self, path: &str) -> Result<String, KvError> {
+        let dir = self
+            .backup_dir
+            .as_ref()
+            .ok_or_else(|| KvError::PermissionDenied("backup dir is not configured".into()))?;
+        let path = backup::resolve_path(dir, path)?;
+        Ok(path.to_string_lossy().into())
+    }
+
+    // 按 table 名的顺序加锁后执行 f，避免多个 table 的命令之间死锁
+    fn with_table_locks<T>(&self, tables: BTreeSet<String>, f: impl FnOnce() -> T) -> T {
+        let locks: Vec<_> = tables
+            .into_iter()
+            .map(|t| (t.clone(), self.table_locks.entry(t).or_default().clone()))
+            .collect();
+        let guards: Vec<_> = locks.iter().map(|(_, l)| l.lock().unwrap()).collect();
+        let res = f();
+        drop(guards);
+
+        // 没有其它命令在使用的锁可以删掉，避免 table_locks 无限增长
+        for (table, lock) in locks {
+            drop(lock);
+            self.table_locks
+                .remove_if(&table, |_, l| Arc::strong_count(l) == 1);
+        }
+        res
+    }
+
+    fn commit(&self, changes: Vec<Change>) {
+        self.indexes.apply(&changes);
+        self.changes.append(changes);
+    }
 }
 
 impl<Store: Storage> Service<Store> {
@@ -104,7 +269,7 @@ impl<Store: Storage> Service<Store> {
     pub fn execute(&self, cmd: CommandRequest) -> CommandResponse {
         debug!("Got request: {:?}", cmd);
         self.inner.on_received.notify(&cmd);
-        let mut res = dispatch(cmd, &self.inner.store);
+        let mut res = self.inner.dispatch(cmd);
         debug!("Executed response: {:?}", res);
         self.inner.on_executed.notify(&res);
         self.inner.on_before_send.notify(&mut res);
@@ -114,6 +279,17 @@ impl<Store: Storage> Service<Store> {
 
         res
     }
+
+    /// 订阅 table 中 key 以 prefix 开头的修改事件
+    pub fn watch(&self, watch: Watch) -> Result<Subscription, KvError> {
+        if is_reserved(&watch.table) {
+            return Err(KvError::PermissionDenied(format!(
+                "table {} is reserved",
+                watch.table
+            )));
+        }
+        self.inner.changes.subscribe(watch)
+    }
 }
 
 impl<Store: Storage> From<ServiceInner<Store>> for Service<Store> {
@@ -128,9 +304,23 @@ pub fn dispatch(cmd: CommandRequest, store: &impl Storage) -> CommandResponse {
     match cmd.request_data {
         Some(RequestData::Hget(param)) => param.execute(store),
         Some(RequestData::Hgetall(param)) => param.execute(store),
+        Some(RequestData::Hmget(param)) => param.execute(store),
         Some(RequestData::Hset(param)) => param.execute(store),
+        Some(RequestData::Hmset(param)) => param.execute(store),
+        Some(RequestData::Hdel(param)) => param.execute(store),
+        Some(RequestData::Hmdel(param)) => param.execute(store),
+        Some(RequestData::Hexist(param)) => param.execute(store),
+        Some(RequestData::Hmexist(param)) => param.execute(store),
+        Some(RequestData::Dump(_)) | Some(RequestData::Restore(_)) => {
+            KvError::InvalidCommand("Dump and Restore must be executed by Service".into()).into()
+        }
+        Some(RequestData::CreateIndex(_)) | Some(RequestData::FindByValue(_)) => {
+            KvError::InvalidCommand("Index commands must be executed by Service".into()).into()
+        }
+        Some(RequestData::Watch(_)) | Some(RequestData::Eval(_)) => {
+            KvError::InvalidCommand("Watch and Eval must be executed by Service".into()).into()
+        }
         None => KvError::InvalidCommand("Request has no data".into()).into(),
-        _ => KvError::Internal("Not implemented".into()).into(),
     }
 }
 
@@ -162,6 +352,22 @@ mod tests {
         assert_res_ok(res, &["v1".into()], &[]);
     }
 
+    #[test]
+    fn table_locks_should_be_removed_after_use() {
+        let service: Service = ServiceInner::new(MemTable::default()).into();
+        service.execute(CommandRequest::new_hset("t1", "k1", "v1".into()));
+        let keys = ["t1", "t2"]
+            .iter()
+            .map(|t| crate::EvalKey {
+                table: t.to_string(),
+                key: "k1".into(),
+            })
+            .collect();
+        let res = service.execute(CommandRequest::new_eval("1", keys, vec![]));
+        assert_eq!(res.status, 200);
+        assert!(service.inner.table_locks.is_empty());
+    }
+
     fn event_registration_should_work() {
         fn b(cmd: &CommandRequest) {
             info!("Got {:?}", cmd);
@@ -188,14 +394,14 @@ mod tests {
             .into();
 
         let res = service.execute(CommandRequest::new_hset("t1", "k1", "v1".into()));
-        assert_eq!(res.status, StatusCode::CREATED.as_u16() as _);
+        assert_eq!(res.status, StatusCode::CREATED.as_u16() as u32);
         assert_eq!(res.message, "");
         assert_eq!(res.values, vec![Value::default()]);
     }
 }
 
 #[cfg(test)]
-use crate::{Kvpair, Value};
+use crate::Kvpair;
 
 #[cfg(test)]
 pub fn assert_res_ok(mut res: CommandResponse, values: &[Value], pairs: &[Kvpair]) {
@@ -206,6 +412,16 @@ pub fn assert_res_ok(mut res: CommandResponse, values: &[Value], pairs: &[Kvpair
     assert_eq!(res.pairs, pairs);
 }
 
+#[cfg(test)]
+pub fn set_key_pairs<T: Into<Value>>(table: &str, pairs: Vec<(&str, T)>, store: &impl Storage) {
+    pairs
+        .into_iter()
+        .map(|(k, v)| CommandRequest::new_hset(table, k, v.into()))
+        .for_each(|cmd| {
+            dispatch(cmd, store);
+        });
+}
+
 pub fn assert_res_error(res: CommandResponse, code: u32, msg: &str) {
     assert_eq!(res.status, code);
     assert!(res.message.contains(msg));
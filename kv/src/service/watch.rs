@@ -0,0 +1,319 @@
+use super::change::Change;
+use crate::{change_event, ChangeEvent, KvError, Watch};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, Weak},
+};
+use tokio::sync::mpsc::{self, error::TrySendError, Receiver, Sender};
+use tracing::{debug, warn};
+
+/// 默认保留的修改事件数量
+pub const DEFAULT_CHANGE_LOG_CAPACITY: usize = 1024;
+
+// 每个订阅者最多缓存的未读事件数量，不包括订阅时回放的事件
+const SUBSCRIBER_BUFFER: usize = 256;
+
+/// 保留最近若干个修改事件的日志，并把新的事件推送给订阅者
+#[derive(Debug)]
+pub(crate) struct ChangeLog {
+    capacity: usize,
+    inner: Arc<Mutex<ChangeLogInner>>,
+}
+
+#[derive(Debug)]
+struct ChangeLogInner {
+    events: VecDeque<ChangeEvent>,
+    next_seq: u64,
+    next_subscriber_id: u64,
+    subscribers: Vec<Subscriber>,
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    id: u64,
+    watch: Watch,
+    tx: Sender<ChangeEvent>,
+}
+
+/// Watch 命令的订阅，drop 时取消订阅
+///
+/// 消费太慢、缓存满了的订阅者会被断开，recv 在取完已缓存的事件后返回 None，
+/// 客户端需要用最后收到的 seq + 1 重新 Watch
+#[derive(Debug)]
+pub struct Subscription {
+    id: u64,
+    rx: Receiver<ChangeEvent>,
+    log: Weak<Mutex<ChangeLogInner>>,
+}
+
+impl Subscription {
+    /// 等待下一个修改事件，订阅被断开后返回 None
+    pub async fn recv(&mut self) -> Option<ChangeEvent> {
+        self.rx.recv().await
+    }
+
+    /// 不阻塞地获取下一个修改事件
+    pub fn try_next(&mut self) -> Option<ChangeEvent> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(log) = self.log.upgrade() {
+            let mut inner = log.lock().unwrap();
+            inner.subscribers.retain(|s| s.id != self.id);
+        }
+    }
+}
+
+impl ChangeLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Arc::new(Mutex::new(ChangeLogInner {
+                events: VecDeque::with_capacity(capacity),
+                next_seq: 1,
+                next_subscriber_id: 1,
+                subscribers: Vec::new(),
+            })),
+        }
+    }
+
+    /// 为变更分配序号，写入日志并推送给订阅者
+    pub fn append(&self, changes: Vec<Change>) {
+        if changes.is_empty() {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        for change in changes {
+            let event = ChangeEvent {
+                seq: inner.next_seq,
+                kind: match change.new {
+                    Some(_) => change_event::Kind::Set as _,
+                    None => change_event::Kind::Del as _,
+                },
+                table: change.table,
+                key: change.key,
+                old_value: change.old,
+                new_value: change.new,
+            };
+            inner.next_seq += 1;
+
+            // 不能阻塞写入，缓存满了的订阅者直接断开，顺便清理掉已经断开的订阅者
+            inner.subscribers.retain(|s| {
+                if !matches(&s.watch, &event) {
+                    return true;
+                }
+                match s.tx.try_send(event.clone()) {
+                    Ok(()) => true,
+                    Err(TrySendError::Full(_)) => {
+                        warn!("Subscriber {} is lagging, disconnect it", s.id);
+                        false
+                    }
+                    Err(TrySendError::Closed(_)) => false,
+                }
+            });
+
+            while inner.events.len() >= self.capacity {
+                inner.events.pop_front();
+            }
+            inner.events.push_back(event);
+        }
+    }
+
+    /// 返回日志中从 watch.from_seq 开始匹配的事件
+    pub fn since(&self, watch: &Watch) -> Result<Vec<ChangeEvent>, KvError> {
+        let inner = self.inner.lock().unwrap();
+        inner.since(watch)
+    }
+
+    /// 订阅修改事件，先回放日志中的事件，之后推送新的事件
+    pub fn subscribe(&self, watch: Watch) -> Result<Subscription, KvError> {
+        // 回放和注册在同一个锁里完成，保证事件不重复也不遗漏
+        let mut inner = self.inner.lock().unwrap();
+        let events = inner.since(&watch)?;
+        let (tx, rx) = mpsc::channel(events.len() + SUBSCRIBER_BUFFER);
+        for event in events {
+            tx.try_send(event).unwrap();
+        }
+
+        let id = inner.next_subscriber_id;
+        inner.next_subscriber_id += 1;
+        debug!("New subscriber {}: {:?}", id, watch);
+        inner.subscribers.push(Subscriber { id, watch, tx });
+        Ok(Subscription {
+            id,
+            rx,
+            log: Arc::downgrade(&self.inner),
+        })
+    }
+}
+
+impl ChangeLogInner {
+    fn since(&self, watch: &Watch) -> Result<Vec<ChangeEvent>, KvError> {
+        if watch.from_seq == 0 {
+            return Ok(vec![]);
+        }
+
+        // seq 在服务重启后会从 1 重新开始，比 next_seq 还大的 seq 来自重启之前，同样已经过期
+        let oldest = self.events.front().map_or(self.next_seq, |e| e.seq);
+        if watch.from_seq < oldest || watch.from_seq > self.next_seq {
+            return Err(KvError::EventsExpired(watch.from_seq, oldest));
+        }
+
+        Ok(self
+            .events
+            .iter()
+            .filter(|e| e.seq >= watch.from_seq && matches(watch, e))
+            .cloned()
+            .collect())
+    }
+}
+
+fn matches(watch: &Watch, event: &ChangeEvent) -> bool {
+    watch.table == event.table && event.key.starts_with(&watch.prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assert_res_ok, CommandRequest, MemTable, Service, ServiceInner, Value};
+
+    fn watch(table: &str, prefix: &str, from_seq: u64) -> Watch {
+        Watch {
+            table: table.into(),
+            prefix: prefix.into(),
+            from_seq,
+        }
+    }
+
+    #[test]
+    fn subscription_should_receive_matched_events() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        let mut sub = service.watch(watch("t1", "user:", 0)).unwrap();
+
+        service.execute(CommandRequest::new_hset("t1", "user:1", "v1".into()));
+        service.execute(CommandRequest::new_hset("t1", "order:1", "v1".into()));
+        service.execute(CommandRequest::new_hset("t2", "user:1", "v1".into()));
+        service.execute(CommandRequest::new_hset("t1", "user:1", "v2".into()));
+        service.execute(CommandRequest::new_hdel("t1", "user:1"));
+        // 删除不存在的 key 不产生事件
+        service.execute(CommandRequest::new_hdel("t1", "user:2"));
+
+        let events: Vec<_> = std::iter::from_fn(|| sub.try_next()).collect();
+        let v1: Value = "v1".into();
+        let v2: Value = "v2".into();
+        assert_eq!(
+            events,
+            vec![
+                event(1, change_event::Kind::Set, "user:1", None, Some(v1.clone())),
+                event(
+                    4,
+                    change_event::Kind::Set,
+                    "user:1",
+                    Some(v1),
+                    Some(v2.clone())
+                ),
+                event(5, change_event::Kind::Del, "user:1", Some(v2), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn watch_should_resume_from_seq() {
+        let service: Service = ServiceInner::new(MemTable::new())
+            .change_log_capacity(2)
+            .into();
+        for i in 0..3 {
+            let cmd = CommandRequest::new_hset("t1", format!("k{}", i), i.into());
+            service.execute(cmd);
+        }
+
+        // 日志中只保留了最后两个事件
+        let res = service.execute(CommandRequest::new_watch("t1", "", 2));
+        let seqs: Vec<_> = res.events.iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![2, 3]);
+
+        let res = service.execute(CommandRequest::new_watch("t1", "", 1));
+        assert_eq!(res.status, 410);
+
+        // 下一个事件的 seq 是 4，更大的 seq 来自重启之前的日志
+        let res = service.execute(CommandRequest::new_watch("t1", "", 4));
+        assert_res_ok(res, &[], &[]);
+        let res = service.execute(CommandRequest::new_watch("t1", "", 5));
+        assert_eq!(res.status, 410);
+        assert!(service.watch(watch("t1", "", 100)).is_err());
+
+        // 订阅时先回放日志中的事件
+        let mut sub = service.watch(watch("t1", "", 3)).unwrap();
+        service.execute(CommandRequest::new_hset("t1", "k3", 3.into()));
+        let seqs: Vec<_> = std::iter::from_fn(|| sub.try_next())
+            .map(|e| e.seq)
+            .collect();
+        assert_eq!(seqs, vec![3, 4]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_change_log_capacity_should_be_rejected() {
+        let _service: Service = ServiceInner::new(MemTable::new())
+            .change_log_capacity(0)
+            .into();
+    }
+
+    #[tokio::test]
+    async fn subscription_should_be_removed_when_dropped_or_lagging() {
+        let service: Service = ServiceInner::new(MemTable::new()).into();
+        let subscribers = || {
+            service
+                .inner
+                .changes
+                .inner
+                .lock()
+                .unwrap()
+                .subscribers
+                .len()
+        };
+        let hset = |i: usize| {
+            let cmd = CommandRequest::new_hset("t1", format!("k{}", i), (i as i64).into());
+            service.execute(cmd);
+        };
+
+        let mut sub = service.watch(watch("t1", "", 0)).unwrap();
+        assert_eq!(subscribers(), 1);
+        hset(0);
+        assert_eq!(sub.recv().await.map(|e| e.seq), Some(1));
+        drop(sub);
+        assert_eq!(subscribers(), 0);
+
+        // 不读取事件的订阅者在缓存满了之后被断开，已缓存的事件仍然可以读取
+        let mut sub = service.watch(watch("t1", "", 0)).unwrap();
+        for i in 0..=SUBSCRIBER_BUFFER {
+            hset(i);
+        }
+        assert_eq!(subscribers(), 0);
+        for _ in 0..SUBSCRIBER_BUFFER {
+            assert!(sub.recv().await.is_some());
+        }
+        assert_eq!(sub.recv().await, None);
+    }
+
+    fn event(
+        seq: u64,
+        kind: change_event::Kind,
+        key: &str,
+        old_value: Option<Value>,
+        new_value: Option<Value>,
+    ) -> ChangeEvent {
+        ChangeEvent {
+            seq,
+            kind: kind as _,
+            table: "t1".into(),
+            key: key.into(),
+            old_value,
+            new_value,
+        }
+    }
+}
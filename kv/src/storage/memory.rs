@@ -72,6 +72,10 @@ impl Storage for MemTable {
         let iter = StorageIter::new(table.into_iter());
         Ok(Box::new(iter))
     }
+
+    fn get_tables(&self) -> Result<Vec<String>, KvError> {
+        Ok(self.tables.iter().map(|t| t.key().clone()).collect())
+    }
 }
 
 impl From<(String, Value)> for Kvpair {
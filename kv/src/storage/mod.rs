@@ -23,6 +23,8 @@ pub trait Storage {
     fn get_all(&self, table: &str) -> Result<Vec<Kvpair>, KvError>;
     /// 遍历HashTable, 返回kv pair 的Iterator
     fn get_iter(&self, table: &str) -> Result<Box<dyn Iterator<Item = Kvpair>>, KvError>;
+    /// 返回所有HashTable的名字
+    fn get_tables(&self) -> Result<Vec<String>, KvError>;
 }
 
 pub struct StorageIter<T> {
@@ -50,6 +52,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::tempdir;
 
     #[test]
     fn memtable_basic_interface_should_work() {
@@ -69,6 +72,19 @@ mod tests {
         test_get_iter(store);
     }
 
+    #[test]
+    fn memtable_get_tables_should_work() {
+        let store = MemTable::new();
+        test_get_tables(store);
+    }
+
+    #[test]
+    fn sleddb_get_tables_should_work() {
+        let dir = tempdir().unwrap();
+        let store = SledDb::new(dir);
+        test_get_tables(store);
+    }
+
     fn test_basi_interface(store: impl Storage) {
         // 第一次 set 会创建 table，插入 key 并返回 None（之前没值）
         let v = store.set("t1", "hello", "world");
@@ -126,4 +142,15 @@ mod tests {
             ]
         )
     }
+
+    fn test_get_tables(store: impl Storage) {
+        store.set("t1", "k1", "v1").unwrap();
+        store.set("t2", "k1", "v1").unwrap();
+        store.set("t2", "k2", "v2").unwrap();
+        // 名字以另一个 table 开头的 table
+        store.set("t10", "k1", "v1").unwrap();
+        let mut tables = store.get_tables().unwrap();
+        tables.sort();
+        assert_eq!(tables, vec!["t1", "t10", "t2"]);
+    }
 }
@@ -8,7 +8,12 @@ pub struct SledDb(Db);
 
 impl SledDb {
     pub fn new(path: impl AsRef<Path>) -> Self {
-        Self(sled::open(path).unwrap())
+        Self::try_new(path).unwrap()
+    }
+
+    /// 和 new 一样，但打开失败时返回错误，比如目录还被其它 sled 实例锁着
+    pub fn try_new(path: impl AsRef<Path>) -> Result<Self, KvError> {
+        Ok(Self(sled::open(path)?))
     }
 
     fn get_full_key(table: &str, key: &str) -> String {
@@ -72,6 +77,20 @@ impl Storage for SledDb {
         let iter = StorageIter::new(self.0.scan_prefix(prefix));
         Ok(Box::new(iter))
     }
+
+    fn get_tables(&self) -> Result<Vec<String>, KvError> {
+        let mut tables: Vec<String> = Vec::new();
+        // key 是有序的，找到一个 table 之后直接跳过它所有的 key，每个 table 只读一个 key
+        let mut start = Vec::new();
+        while let Some(key) = self.0.range(start..).keys().next() {
+            let key = key?;
+            let name = ivec_to_table(key.as_ref())?;
+            // "table:" 开头的 key 都小于 "table;"
+            start = format!("{};", name).into_bytes();
+            tables.push(name.into());
+        }
+        Ok(tables)
+    }
 }
 
 impl From<Result<(IVec, IVec), sled::Error>> for Kvpair {
@@ -86,9 +105,15 @@ impl From<Result<(IVec, IVec), sled::Error>> for Kvpair {
     }
 }
 
+fn ivec_to_table(ivec: &[u8]) -> Result<&str, KvError> {
+    let s = str::from_utf8(ivec)
+        .map_err(|_| KvError::Internal(format!("invalid key in sled: {:?}", ivec)))?;
+    Ok(s.split(':').next().unwrap_or_default())
+}
+
 fn ivec_to_key(ivec: &[u8]) -> &str {
     let s = str::from_utf8(ivec).unwrap();
-    let mut iter = s.split(":");
+    let mut iter = s.split(':');
     iter.next();
     iter.next().unwrap()
 }
@@ -0,0 +1,361 @@
+use crate::config::env_or;
+use anyhow::Result;
+use bytes::Bytes;
+use lru::LruCache;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+use tokio::fs;
+use tracing::{info, warn};
+
+/// 缓存的配置
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// 内存缓存的字节数上限
+    pub memory_size: usize,
+    /// 内存缓存分成多少个分片，每个分片有自己的锁，平分字节数上限
+    pub memory_shards: usize,
+    /// 磁盘缓存的目录，为 None 时只使用内存缓存
+    pub disk_dir: Option<PathBuf>,
+    /// 磁盘缓存的字节数上限
+    pub disk_size: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            memory_size: 256 * 1024 * 1024,
+            memory_shards: 16,
+            disk_dir: None,
+            disk_size: 4 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// 从环境变量读取配置，没有设置的使用默认值
+    pub fn from_env() -> Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            memory_size: env_or("THUMBOR_CACHE_MEMORY_SIZE", default.memory_size)?,
+            memory_shards: default.memory_shards,
+            disk_dir: std::env::var_os("THUMBOR_CACHE_DIR").map(PathBuf::from),
+            disk_size: env_or("THUMBOR_CACHE_DISK_SIZE", default.disk_size)?,
+        })
+    }
+}
+
+/// 两级缓存：按字节数淘汰的内存 LRU，后面是有大小限制的磁盘存储
+/// key 是内容的 hash（或者由 hash 推导出来），同一个 key 对应的内容不变
+#[derive(Clone)]
+pub struct Cache {
+    inner: Arc<CacheInner>,
+}
+
+struct CacheInner {
+    memory: Vec<Mutex<MemoryTier>>,
+    disk: Option<DiskTier>,
+}
+
+impl Cache {
+    pub async fn new(config: CacheConfig) -> Result<Self> {
+        let disk = match config.disk_dir {
+            Some(dir) => Some(DiskTier::open(dir, config.disk_size).await?),
+            None => None,
+        };
+        let shards = config.memory_shards.max(1);
+        let memory = (0..shards)
+            .map(|_| Mutex::new(MemoryTier::new(config.memory_size / shards)))
+            .collect();
+        Ok(Self {
+            inner: Arc::new(CacheInner { memory, disk }),
+        })
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Bytes> {
+        let data = self.shard(key).lock().unwrap().get(key);
+        if data.is_some() {
+            return data;
+        }
+
+        let data = self.inner.disk.as_ref()?.get(key).await?;
+        // 从磁盘读到的放回内存
+        self.shard(key)
+            .lock()
+            .unwrap()
+            .put(key.to_owned(), data.clone());
+        Some(data)
+    }
+
+    pub async fn put(&self, key: String, data: Bytes) {
+        if let Some(ref disk) = self.inner.disk {
+            disk.put(&key, &data).await;
+        }
+        self.shard(&key).lock().unwrap().put(key, data);
+    }
+
+    fn shard(&self, key: &str) -> &Mutex<MemoryTier> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let shards = &self.inner.memory;
+        &shards[hasher.finish() as usize % shards.len()]
+    }
+}
+
+// 内存缓存，按照字节数而不是条目数淘汰
+struct MemoryTier {
+    entries: LruCache<String, Bytes>,
+    size: usize,
+    capacity: usize,
+}
+
+impl MemoryTier {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: LruCache::unbounded(),
+            size: 0,
+            capacity,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Bytes> {
+        self.entries.get(&key.to_owned()).cloned()
+    }
+
+    fn put(&mut self, key: String, data: Bytes) {
+        // 比整个缓存还大的不缓存
+        if data.len() > self.capacity {
+            return;
+        }
+        self.size += data.len();
+        if let Some(old) = self.entries.put(key, data) {
+            self.size -= old.len();
+        }
+        while self.size > self.capacity {
+            match self.entries.pop_lru() {
+                Some((_, v)) => self.size -= v.len(),
+                None => break,
+            }
+        }
+    }
+}
+
+// 磁盘缓存，文件保存在 dir/<key 前两个字符>/<key>
+// 内存中记录每个文件的大小和访问顺序，启动时按修改时间恢复
+struct DiskTier {
+    dir: PathBuf,
+    capacity: u64,
+    index: Mutex<DiskIndex>,
+    // 用于生成不重复的临时文件名
+    seq: AtomicU64,
+}
+
+struct DiskIndex {
+    entries: LruCache<String, u64>,
+    size: u64,
+}
+
+impl DiskTier {
+    async fn open(dir: PathBuf, capacity: u64) -> Result<Self> {
+        fs::create_dir_all(&dir).await?;
+
+        let mut files = Vec::new();
+        let mut dirs = fs::read_dir(&dir).await?;
+        while let Some(sub) = dirs.next_entry().await? {
+            if !sub.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut entries = fs::read_dir(sub.path()).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                // 上次没有写完的临时文件
+                if name.ends_with(".tmp") {
+                    fs::remove_file(entry.path()).await.ok();
+                    continue;
+                }
+                let meta = entry.metadata().await?;
+                let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                files.push((modified, name, meta.len()));
+            }
+        }
+
+        // 最近修改的最后放入，淘汰时最后被淘汰
+        files.sort();
+        let tier = Self {
+            dir,
+            capacity,
+            index: Mutex::new(DiskIndex {
+                entries: LruCache::unbounded(),
+                size: 0,
+            }),
+            seq: AtomicU64::new(0),
+        };
+        let evicted = {
+            let mut index = tier.index.lock().unwrap();
+            for (_, key, len) in files {
+                index.size += len;
+                index.entries.put(key, len);
+            }
+            info!(
+                "Open disk cache {:?}: {} files, {} bytes",
+                tier.dir,
+                index.entries.len(),
+                index.size
+            );
+            index.evict(capacity)
+        };
+        tier.remove(evicted).await;
+        Ok(tier)
+    }
+
+    async fn get(&self, key: &str) -> Option<Bytes> {
+        let key = key.to_owned();
+        // get 同时会更新访问顺序
+        self.index.lock().unwrap().entries.get(&key)?;
+
+        match fs::read(self.path(&key)).await {
+            Ok(data) => Some(data.into()),
+            Err(e) => {
+                warn!("Failed to read cache file {}: {}", key, e);
+                let mut index = self.index.lock().unwrap();
+                if let Some(len) = index.entries.pop(&key) {
+                    index.size -= len;
+                }
+                None
+            }
+        }
+    }
+
+    async fn put(&self, key: &str, data: &[u8]) {
+        let len = data.len() as u64;
+        if len > self.capacity {
+            return;
+        }
+
+        // 先写临时文件再改名，避免读到写了一半的文件
+        let path = self.path(key);
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        let tmp = path.with_file_name(format!("{}.{}.tmp", key, seq));
+        let result = async {
+            fs::create_dir_all(path.parent().unwrap()).await?;
+            fs::write(&tmp, data).await?;
+            fs::rename(&tmp, &path).await
+        };
+        if let Err(e) = result.await {
+            warn!("Failed to write cache file {}: {}", key, e);
+            fs::remove_file(&tmp).await.ok();
+            return;
+        }
+
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            index.size += len;
+            if let Some(old) = index.entries.put(key.to_owned(), len) {
+                index.size -= old;
+            }
+            index.evict(self.capacity)
+        };
+        self.remove(evicted).await;
+    }
+
+    async fn remove(&self, keys: Vec<String>) {
+        for key in keys {
+            if let Err(e) = fs::remove_file(self.path(&key)).await {
+                warn!("Failed to remove cache file {}: {}", key, e);
+            }
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let prefix = key.get(..2).unwrap_or(key);
+        self.dir.join(prefix).join(key)
+    }
+}
+
+impl DiskIndex {
+    // 淘汰最久没有访问的文件，返回需要删除的 key
+    fn evict(&mut self, capacity: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.size > capacity {
+            match self.entries.pop_lru() {
+                Some((key, len)) => {
+                    self.size -= len;
+                    evicted.push(key);
+                }
+                None => break,
+            }
+        }
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn config(memory_size: usize, disk_dir: Option<&Path>, disk_size: u64) -> CacheConfig {
+        CacheConfig {
+            memory_size,
+            memory_shards: 1,
+            disk_dir: disk_dir.map(Path::to_path_buf),
+            disk_size,
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_cache_should_be_bounded_by_bytes() {
+        let cache = Cache::new(config(10, None, 0)).await.unwrap();
+        cache.put("k1".into(), Bytes::from("12345")).await;
+        cache.put("k2".into(), Bytes::from("12345")).await;
+        // 访问 k1，k2 变成最久没有访问的
+        assert_eq!(cache.get("k1").await, Some(Bytes::from("12345")));
+        cache.put("k3".into(), Bytes::from("123")).await;
+        assert!(cache.get("k2").await.is_none());
+        assert!(cache.get("k1").await.is_some());
+        assert!(cache.get("k3").await.is_some());
+
+        // 超过整个缓存大小的不缓存
+        cache.put("k4".into(), Bytes::from("12345678901")).await;
+        assert!(cache.get("k4").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn disk_cache_should_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(config(0, Some(dir.path()), 1024)).await.unwrap();
+        cache.put("abcdef".into(), Bytes::from("hello")).await;
+        drop(cache);
+
+        let cache = Cache::new(config(1024, Some(dir.path()), 1024))
+            .await
+            .unwrap();
+        assert_eq!(cache.get("abcdef").await, Some(Bytes::from("hello")));
+        assert!(cache.get("missing").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn disk_cache_should_be_bounded_by_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(config(0, Some(dir.path()), 10)).await.unwrap();
+        cache.put("k1".into(), Bytes::from("12345")).await;
+        cache.put("k2".into(), Bytes::from("12345")).await;
+        assert!(cache.get("k1").await.is_some());
+        cache.put("k3".into(), Bytes::from("123")).await;
+        assert!(cache.get("k2").await.is_none());
+        assert!(!dir.path().join("k2").join("k2").exists());
+
+        // 重新打开时按新的上限淘汰
+        drop(cache);
+        let cache = Cache::new(config(0, Some(dir.path()), 3)).await.unwrap();
+        assert!(cache.get("k1").await.is_none());
+        assert!(cache.get("k3").await.is_some());
+    }
+}
@@ -0,0 +1,27 @@
+use anyhow::{anyhow, Result};
+use std::{env, fmt::Display, str::FromStr};
+
+/// 逗号分隔的列表，没有设置时返回 None
+pub fn env_list(name: &str) -> Option<Vec<String>> {
+    let v = env::var(name).ok()?;
+    Some(
+        v.split(',')
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect(),
+    )
+}
+
+/// 解析环境变量，没有设置时使用默认值
+pub fn env_or<T>(name: &str, default: T) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(v) => v
+            .parse()
+            .map_err(|e| anyhow!("invalid {} {:?}: {}", name, v, e)),
+        Err(_) => Ok(default),
+    }
+}
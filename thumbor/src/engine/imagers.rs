@@ -0,0 +1,263 @@
+use super::{
+    check, draw_text, encode, pad, rotate,
+    smart::{find_region, fit, scaled_size},
+    watermark, Engine, EngineError, SpecTransform, Watermarks,
+};
+use crate::pb::*;
+use bytes::Bytes;
+use image::{imageops, RgbaImage};
+use imageproc::seam_carving::shrink_width;
+
+/// 只依赖 image/imageproc 的 Engine
+pub struct ImageRs(RgbaImage);
+
+impl TryFrom<Bytes> for ImageRs {
+    type Error = EngineError;
+
+    fn try_from(data: Bytes) -> Result<Self, Self::Error> {
+        let img = image::load_from_memory(&data).map_err(|e| EngineError::Decode(e.to_string()))?;
+        Ok(Self(img.to_rgba8()))
+    }
+}
+
+impl Engine for ImageRs {
+    fn dimensions(&self) -> (u32, u32) {
+        self.0.dimensions()
+    }
+
+    fn apply(&mut self, specs: &[Spec], watermarks: &Watermarks) -> Result<(), EngineError> {
+        for spec in specs.iter() {
+            check(spec, self.dimensions())?;
+            match spec.data {
+                Some(spec::Data::Crop(ref v)) => self.transform(v)?,
+                Some(spec::Data::Contrast(ref v)) => self.transform(v)?,
+                Some(spec::Data::Filter(ref v)) => self.transform(v)?,
+                Some(spec::Data::Fliph(ref v)) => self.transform(v)?,
+                Some(spec::Data::Flipv(ref v)) => self.transform(v)?,
+                Some(spec::Data::Resize(ref v)) => self.transform(v)?,
+                Some(spec::Data::Watermark(ref v)) => self.transform((v, watermarks.image(v)?))?,
+                Some(spec::Data::Rotate(ref v)) => self.transform(v)?,
+                Some(spec::Data::Blur(ref v)) => self.transform(v)?,
+                Some(spec::Data::Sharpen(ref v)) => self.transform(v)?,
+                Some(spec::Data::Brightness(ref v)) => self.transform(v)?,
+                Some(spec::Data::Grayscale(ref v)) => self.transform(v)?,
+                Some(spec::Data::Sepia(ref v)) => self.transform(v)?,
+                Some(spec::Data::Padding(ref v)) => self.transform(v)?,
+                Some(spec::Data::Text(ref v)) => self.transform(v)?,
+                // 输出格式在 generate 时处理
+                Some(spec::Data::Output(_)) => {}
+
+                // 对于目前不支持的spec 不做任何处理
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn generate(self, output: &Output) -> Result<Vec<u8>, EngineError> {
+        encode(self.0, output.format(), output.quality_or_default())
+    }
+}
+
+impl SpecTransform<&Crop> for ImageRs {
+    fn transform(&mut self, op: &Crop) -> Result<(), EngineError> {
+        let (x, y, width, height) = match (op.width, op.height) {
+            (0, 0) => (op.x1, op.y1, op.x2 - op.x1, op.y2 - op.y1),
+            (w, h) => {
+                let (x, y) = find_region(&self.0, w, h, op.gravity());
+                (x, y, w, h)
+            }
+        };
+        self.0 = imageops::crop_imm(&self.0, x, y, width, height).to_image();
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Contrast> for ImageRs {
+    fn transform(&mut self, op: &Contrast) -> Result<(), EngineError> {
+        self.0 = contrast(&self.0, op.contrast);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Flipv> for ImageRs {
+    fn transform(&mut self, _op: &Flipv) -> Result<(), EngineError> {
+        self.0 = imageops::flip_vertical(&self.0);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Fliph> for ImageRs {
+    fn transform(&mut self, _op: &Fliph) -> Result<(), EngineError> {
+        self.0 = imageops::flip_horizontal(&self.0);
+        Ok(())
+    }
+}
+
+// 滤镜用混合颜色、灰度和对比度近似 photon 的效果
+impl SpecTransform<&Filter> for ImageRs {
+    fn transform(&mut self, op: &Filter) -> Result<(), EngineError> {
+        let img = &mut self.0;
+        match filter::Filter::from_i32(op.filter) {
+            Some(filter::Filter::Oceanic) => tint(img, [0, 89, 173], 0.2),
+            Some(filter::Filter::Islands) => tint(img, [0, 24, 95], 0.2),
+            Some(filter::Filter::Marine) => tint(img, [0, 14, 119], 0.2),
+            Some(filter::Filter::Seagreen) => tint(img, [0, 68, 62], 0.2),
+            Some(filter::Filter::Flagblue) => tint(img, [0, 0, 131], 0.2),
+            Some(filter::Filter::Liquid) => tint(img, [0, 10, 75], 0.2),
+            Some(filter::Filter::Diamante) => tint(img, [30, 82, 87], 0.1),
+            Some(filter::Filter::Radio) => tint(img, [0, 0, 0], 0.2),
+            Some(filter::Filter::Twenties) => tint(img, [120, 70, 13], 0.1),
+            Some(filter::Filter::Rosetint) => tint(img, [255, 105, 180], 0.2),
+            Some(filter::Filter::Mauve) => tint(img, [90, 40, 112], 0.2),
+            Some(filter::Filter::Bluechrome) => tint(img, [8, 44, 114], 0.2),
+            Some(filter::Filter::Vintage) => tint(img, [120, 70, 13], 0.2),
+            Some(filter::Filter::Perfume) => tint(img, [80, 40, 120], 0.2),
+            Some(filter::Filter::Serenity) => tint(img, [10, 40, 90], 0.2),
+            Some(filter::Filter::Golden) => tint(img, [255, 215, 0], 0.15),
+            Some(filter::Filter::Lofi) => *img = contrast(img, 40.0),
+            Some(filter::Filter::PastelPink) => tint(img, [220, 112, 170], 0.1),
+            Some(filter::Filter::Cali) => tint(img, [255, 170, 90], 0.1),
+            Some(filter::Filter::Dramatic) => {
+                grayscale(img);
+                *img = contrast(img, 60.0);
+            }
+            Some(filter::Filter::Firenze) => tint(img, [255, 47, 78], 0.1),
+            Some(filter::Filter::Obsidian) => {
+                grayscale(img);
+                *img = contrast(img, 25.0);
+            }
+            Some(filter::Filter::Unspecified) | None => {}
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Resize> for ImageRs {
+    fn transform(&mut self, op: &Resize) -> Result<(), EngineError> {
+        let (width, height) = scaled_size(self.dimensions(), op);
+        let img = match op.rtype() {
+            resize::ResizeType::Normal => {
+                imageops::resize(&self.0, width, height, op.filter().into())
+            }
+            resize::ResizeType::SeamCarve => seam_carve(&self.0, width, height),
+        };
+
+        self.0 = fit(img, op);
+        Ok(())
+    }
+}
+
+impl SpecTransform<(&Watermark, RgbaImage)> for ImageRs {
+    fn transform(&mut self, (op, mark): (&Watermark, RgbaImage)) -> Result<(), EngineError> {
+        watermark(&mut self.0, mark, op);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Rotate> for ImageRs {
+    fn transform(&mut self, op: &Rotate) -> Result<(), EngineError> {
+        if let Some(img) = rotate(&self.0, op.angle) {
+            self.0 = img;
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Blur> for ImageRs {
+    fn transform(&mut self, op: &Blur) -> Result<(), EngineError> {
+        if op.radius > 0 {
+            self.0 = imageops::blur(&self.0, op.radius as f32 / 2.0);
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Sharpen> for ImageRs {
+    fn transform(&mut self, _op: &Sharpen) -> Result<(), EngineError> {
+        self.0 = imageops::unsharpen(&self.0, 1.0, 1);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Brightness> for ImageRs {
+    fn transform(&mut self, op: &Brightness) -> Result<(), EngineError> {
+        self.0 = imageops::brighten(&self.0, op.brightness.clamp(-255, 255));
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Grayscale> for ImageRs {
+    fn transform(&mut self, _op: &Grayscale) -> Result<(), EngineError> {
+        grayscale(&mut self.0);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Sepia> for ImageRs {
+    fn transform(&mut self, _op: &Sepia) -> Result<(), EngineError> {
+        for p in self.0.pixels_mut() {
+            let [r, g, b, a] = p.0.map(|v| v as f32);
+            let sepia = |kr: f32, kg: f32, kb: f32| (r * kr + g * kg + b * kb).min(255.0) as u8;
+            p.0 = [
+                sepia(0.393, 0.769, 0.189),
+                sepia(0.349, 0.686, 0.168),
+                sepia(0.272, 0.534, 0.131),
+                a as u8,
+            ];
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Padding> for ImageRs {
+    fn transform(&mut self, op: &Padding) -> Result<(), EngineError> {
+        self.0 = pad(&self.0, op);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Text> for ImageRs {
+    fn transform(&mut self, op: &Text) -> Result<(), EngineError> {
+        draw_text(&mut self.0, op)
+    }
+}
+
+// imageproc 的 seam carving 只能缩小宽度，高度通过旋转后缩小宽度实现，放大时直接缩放
+fn seam_carve(img: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let mut img = img.clone();
+    if width < img.width() {
+        img = shrink_width(&img, width);
+    }
+    if height < img.height() {
+        img = imageops::rotate270(&shrink_width(&imageops::rotate90(&img), height));
+    }
+    if img.dimensions() != (width, height) {
+        img = imageops::resize(&img, width, height, imageops::FilterType::Lanczos3);
+    }
+    img
+}
+
+// photon 的对比度范围是 [-255, 255]，换算成 image 使用的百分比
+fn contrast(img: &RgbaImage, contrast: f32) -> RgbaImage {
+    let c = contrast.clamp(-255.0, 255.0);
+    let factor = (259.0 * (c + 255.0)) / (255.0 * (259.0 - c));
+    imageops::contrast(img, (factor.sqrt() - 1.0) * 100.0)
+}
+
+fn grayscale(img: &mut RgbaImage) {
+    for p in img.pixels_mut() {
+        let [r, g, b, a] = p.0;
+        let l = (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) as u8;
+        p.0 = [l, l, l, a];
+    }
+}
+
+// 按比例混合颜色，保留 alpha
+fn tint(img: &mut RgbaImage, color: [u8; 3], opacity: f32) {
+    for p in img.pixels_mut() {
+        for (v, c) in p.0.iter_mut().zip(color) {
+            *v = (*v as f32 * (1.0 - opacity) + c as f32 * opacity) as u8;
+        }
+    }
+}
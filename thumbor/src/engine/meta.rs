@@ -0,0 +1,245 @@
+use super::EngineError;
+use crate::pb::{output, Spec};
+use bytes::Bytes;
+use exif::{Exif, In, Reader, Tag};
+use img_parts::{DynImage, ImageEXIF, ImageICC};
+use serde::Serialize;
+use std::{collections::BTreeMap, io::Cursor};
+
+// EXIF 中方向的 tag
+const ORIENTATION: u16 = 0x0112;
+
+/// 原图的信息，由 /meta 返回
+#[derive(Debug, Serialize)]
+pub struct Meta {
+    pub format: String,
+    // 按 EXIF 方向转正之后的宽和高
+    pub width: u32,
+    pub height: u32,
+    pub orientation: u32,
+    // 是否带有 ICC 颜色配置
+    pub icc: bool,
+    pub exif: BTreeMap<String, String>,
+}
+
+impl Meta {
+    pub fn read(data: &[u8]) -> Result<Self, EngineError> {
+        let decode = |e: image::ImageError| EngineError::Decode(e.to_string());
+        let format = image::guess_format(data).map_err(decode)?;
+        let (width, height) = image::io::Reader::with_format(Cursor::new(data), format)
+            .into_dimensions()
+            .map_err(decode)?;
+
+        let exif = read_exif(data);
+        let orientation = exif.as_ref().map_or(1, orientation_of);
+        let (width, height) = match orientation {
+            5..=8 => (height, width),
+            _ => (width, height),
+        };
+        // 缩略图的字段和厂商私有的 MakerNote 没有意义，不返回
+        let fields = exif
+            .iter()
+            .flat_map(|exif| {
+                exif.fields()
+                    .filter(|f| f.ifd_num == In::PRIMARY && f.tag != Tag::MakerNote)
+                    .map(move |f| {
+                        let value = f.display_value().with_unit(exif).to_string();
+                        (f.tag.to_string(), value)
+                    })
+            })
+            .collect();
+
+        Ok(Self {
+            format: format!("{:?}", format).to_lowercase(),
+            width,
+            height,
+            orientation,
+            icc: icc_profile(data).is_some(),
+            exif: fields,
+        })
+    }
+}
+
+/// 原图 EXIF 中的方向，取值 1-8，没有时返回 1
+pub fn orientation(data: &[u8]) -> u32 {
+    read_exif(data).map_or(1, |exif| orientation_of(&exif))
+}
+
+/// 把图片转正需要的 spec，在请求的 spec 之前执行
+pub fn orient(orientation: u32) -> Vec<Spec> {
+    match orientation {
+        2 => vec![Spec::new_fliph()],
+        3 => vec![Spec::new_rotate(180.0)],
+        4 => vec![Spec::new_flipv()],
+        5 => vec![Spec::new_rotate(90.0), Spec::new_fliph()],
+        6 => vec![Spec::new_rotate(90.0)],
+        7 => vec![Spec::new_rotate(270.0), Spec::new_fliph()],
+        8 => vec![Spec::new_rotate(270.0)],
+        _ => vec![],
+    }
+}
+
+/// 按 output 的设置把原图的元数据写入处理后的图片
+/// 只有 JPEG/PNG/WebP 可以写入，其他格式原样返回
+pub fn copy_metadata(
+    source: &[u8],
+    data: Vec<u8>,
+    metadata: output::Metadata,
+) -> Result<Vec<u8>, EngineError> {
+    if metadata == output::Metadata::Strip {
+        return Ok(data);
+    }
+    let data = Bytes::from(data);
+    let mut img = match DynImage::from_bytes(data.clone()) {
+        Ok(Some(img)) => img,
+        _ => return Ok(data.to_vec()),
+    };
+
+    img.set_icc_profile(icc_profile(source));
+    if metadata == output::Metadata::Keep {
+        img.set_exif(read_exif(source).map(|exif| reset_orientation(&exif)));
+    }
+
+    let mut buf = Vec::with_capacity(data.len());
+    img.encoder()
+        .write_to(&mut buf)
+        .map_err(|e| EngineError::Encode(e.to_string()))?;
+    Ok(buf)
+}
+
+fn read_exif(data: &[u8]) -> Option<Exif> {
+    Reader::new()
+        .read_from_container(&mut Cursor::new(data))
+        .ok()
+}
+
+fn orientation_of(exif: &Exif) -> u32 {
+    exif.get_field(Tag::Orientation, In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        .filter(|v| (1..=8).contains(v))
+        .unwrap_or(1)
+}
+
+fn icc_profile(data: &[u8]) -> Option<Bytes> {
+    let img = DynImage::from_bytes(Bytes::copy_from_slice(data)).ok()??;
+    img.icc_profile()
+}
+
+// 图片已经转正，把 EXIF 中的方向改为 1，避免查看时再旋转一次
+fn reset_orientation(exif: &Exif) -> Bytes {
+    let mut tiff = exif.buf().to_vec();
+    let le = exif.little_endian();
+    if let Some(offset) = orientation_offset(&tiff, le) {
+        let value = if le {
+            1u16.to_le_bytes()
+        } else {
+            1u16.to_be_bytes()
+        };
+        tiff[offset..offset + 2].copy_from_slice(&value);
+    }
+    tiff.into()
+}
+
+// 方向在 IFD0 中的位置，每个条目 12 个字节，值在条目的最后 4 个字节
+fn orientation_offset(tiff: &[u8], le: bool) -> Option<usize> {
+    let read = |offset: usize, len: usize| -> Option<u32> {
+        let bytes = tiff.get(offset..offset + len)?;
+        let fold = |acc: u32, b: &u8| acc << 8 | *b as u32;
+        Some(match le {
+            true => bytes.iter().rev().fold(0, fold),
+            false => bytes.iter().fold(0, fold),
+        })
+    };
+    let ifd = read(4, 4)? as usize;
+    let count = read(ifd, 2)? as usize;
+    (0..count)
+        .map(|i| ifd + 2 + i * 12)
+        .find(|&entry| read(entry, 2) == Some(ORIENTATION as u32))
+        .map(|entry| entry + 8)
+        .filter(|&offset| offset + 2 <= tiff.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{EngineKind, Watermarks};
+    use crate::pb::Output;
+    use image::{DynamicImage, GenericImageView, ImageOutputFormat, Rgba, RgbaImage};
+
+    // 只有方向一个字段的 EXIF，大端
+    fn exif(orientation: u8) -> Bytes {
+        let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+        tiff.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0]);
+        tiff.extend_from_slice(&[0, 0, 0, 0]);
+        tiff.into()
+    }
+
+    // 200x100 的 JPEG，左半边是红色，带有指定的方向
+    fn photo(orientation: u8) -> Bytes {
+        let img = RgbaImage::from_fn(200, 100, |x, _| match x < 100 {
+            true => Rgba([255, 0, 0, 255]),
+            false => Rgba([0, 0, 0, 255]),
+        });
+        let img = DynamicImage::ImageRgba8(img);
+        let mut data = Vec::new();
+        img.write_to(&mut data, ImageOutputFormat::Jpeg(80))
+            .unwrap();
+        let mut jpeg = DynImage::from_bytes(data.into()).unwrap().unwrap();
+        jpeg.set_exif(Some(exif(orientation)));
+        jpeg.encoder().bytes()
+    }
+
+    fn process(data: Bytes, metadata: output::Metadata) -> Vec<u8> {
+        let output = Output {
+            format: output::Format::Jpeg as _,
+            metadata: metadata as _,
+            ..Default::default()
+        };
+        EngineKind::ImageRs
+            .process(data, &[], &Watermarks::default(), &output)
+            .unwrap()
+    }
+
+    #[test]
+    fn meta_should_be_read() {
+        let meta = Meta::read(&photo(6)).unwrap();
+        assert_eq!(meta.format, "jpeg");
+        assert_eq!((meta.width, meta.height, meta.orientation), (100, 200, 6));
+        assert!(!meta.icc);
+        assert_eq!(
+            meta.exif.get("Orientation").unwrap(),
+            "row 0 at right and column 0 at top"
+        );
+
+        assert!(matches!(Meta::read(b"foo"), Err(EngineError::Decode(_))));
+    }
+
+    #[test]
+    fn image_should_be_oriented() {
+        // 原图左上角的红色在转正后所在的位置
+        for (orientation, size, red) in [
+            (1, (200, 100), (10, 10)),
+            (3, (200, 100), (190, 90)),
+            (6, (100, 200), (90, 10)),
+            (8, (100, 200), (10, 190)),
+        ] {
+            let data = process(photo(orientation), output::Metadata::Strip);
+            let img = image::load_from_memory(&data).unwrap();
+            assert_eq!(img.dimensions(), size, "orientation {}", orientation);
+            let (x, y) = red;
+            assert!(
+                img.get_pixel(x, y).0[0] > 200,
+                "orientation {}",
+                orientation
+            );
+            assert!(read_exif(&data).is_none());
+        }
+
+        // 保留 EXIF 时方向重置为 1
+        let data = process(photo(6), output::Metadata::Keep);
+        let exif = read_exif(&data).unwrap();
+        assert!(exif.get_field(Tag::Orientation, In::PRIMARY).is_some());
+        assert_eq!(orientation(&data), 1);
+        assert!(read_exif(&process(photo(6), output::Metadata::Icc)).is_none());
+    }
+}
@@ -1,15 +1,660 @@
-use crate::pb::Spec;
-use image::ImageOutputFormat;
+use crate::pb::{
+    filter, output, resize, spec, watermark, Gravity, Output, Padding, Spec, Text, Watermark,
+    WatermarkSource,
+};
+use anyhow::anyhow;
+use axum::http::StatusCode;
+use bytes::Bytes;
+use image::{imageops, DynamicImage, ImageOutputFormat, Rgba, RgbaImage};
+use imageproc::{
+    drawing::draw_text_mut,
+    geometric_transformations::{rotate_about_center, Interpolation},
+};
+use lazy_static::lazy_static;
+use rusttype::{Font, Scale};
+use std::{collections::HashMap, env, fs, path::Path, str::FromStr, sync::Mutex};
+use thiserror::Error;
+use tracing::warn;
 
+mod imagers;
+mod meta;
 mod photon;
+mod smart;
 
+pub use imagers::ImageRs;
+pub use meta::Meta;
 pub use photon::Photon;
 
+/// 处理图片时的错误，spec 和原图的问题返回 400，其他的返回 500
+#[derive(Error, Debug)]
+pub enum EngineError {
+    #[error("Cannot decode image: {0}")]
+    Decode(String),
+    #[error("Invalid spec {0}: {1}")]
+    InvalidSpec(String, String),
+    #[error("Cannot encode image: {0}")]
+    Encode(String),
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl EngineError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Decode(_) | Self::InvalidSpec(..) => StatusCode::BAD_REQUEST,
+            Self::Encode(_) | Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
 pub trait Engine {
-    fn apply(&mut self, specs: &[Spec]);
-    fn generate(self, format: ImageOutputFormat) -> Vec<u8>;
+    // 当前图片的宽和高，用于检查 spec
+    fn dimensions(&self) -> (u32, u32);
+    fn apply(&mut self, specs: &[Spec], watermarks: &Watermarks) -> Result<(), EngineError>;
+    // output 的格式需要已经确定，不能是 Auto
+    fn generate(self, output: &Output) -> Result<Vec<u8>, EngineError>;
 }
 
 pub trait SpecTransform<T> {
-    fn transform(&mut self, op: T);
+    // 调用前 spec 已经通过 check 检查
+    fn transform(&mut self, op: T) -> Result<(), EngineError>;
+}
+
+/// 使用哪个 Engine 处理图片，每个部署通过 THUMBOR_ENGINE 选择
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EngineKind {
+    #[default]
+    Photon,
+    ImageRs,
+}
+
+impl FromStr for EngineKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "photon" => Ok(Self::Photon),
+            "image" => Ok(Self::ImageRs),
+            _ => Err(anyhow!("unknown engine {:?}, expected photon or image", s)),
+        }
+    }
+}
+
+impl EngineKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Photon => "photon",
+            Self::ImageRs => "image",
+        }
+    }
+
+    /// 用选定的 Engine 处理图片
+    pub fn process(
+        self,
+        data: Bytes,
+        specs: &[Spec],
+        watermarks: &Watermarks,
+        output: &Output,
+    ) -> Result<Vec<u8>, EngineError> {
+        match self {
+            Self::Photon => process::<Photon>(data, specs, watermarks, output),
+            Self::ImageRs => process::<ImageRs>(data, specs, watermarks, output),
+        }
+    }
+}
+
+fn process<E>(
+    data: Bytes,
+    specs: &[Spec],
+    watermarks: &Watermarks,
+    output: &Output,
+) -> Result<Vec<u8>, EngineError>
+where
+    E: Engine + TryFrom<Bytes, Error = EngineError>,
+{
+    // 先按 EXIF 中的方向把图片转正，之后的 spec 都基于转正后的图片
+    let mut engine = E::try_from(data.clone())?;
+    engine.apply(&meta::orient(meta::orientation(&data)), watermarks)?;
+    engine.apply(specs, watermarks)?;
+    let image = engine.generate(output)?;
+    meta::copy_metadata(&data, image, output.metadata())
+}
+
+/// spec 中用到的水印图片，处理之前由调用者准备好
+#[derive(Debug, Clone, Default)]
+pub struct Watermarks(HashMap<WatermarkSource, Bytes>);
+
+impl Watermarks {
+    pub fn insert(&mut self, source: WatermarkSource, data: Bytes) {
+        self.0.insert(source, data);
+    }
+
+    // 没有指定来源时使用内置的水印
+    fn image(&self, op: &Watermark) -> Result<RgbaImage, EngineError> {
+        let source = match op.source() {
+            Some(source) => source,
+            None => return Ok(DEFAULT_WATERMARK.clone()),
+        };
+        let data = self.0.get(&source).ok_or_else(|| {
+            EngineError::Internal(format!("watermark {:?} is not loaded", source))
+        })?;
+        let img = image::load_from_memory(data)
+            .map_err(|e| EngineError::Decode(format!("watermark {:?}: {}", source, e)))?;
+        Ok(img.to_rgba8())
+    }
+}
+
+/// 处理后图片的宽和高都不能超过这个值
+pub const MAX_DIMENSION: u32 = 8192;
+/// 模糊半径的上限，半径越大处理越慢
+pub const MAX_BLUR_RADIUS: u32 = 100;
+
+/// 根据当前图片的宽和高检查 spec，避免越界或者生成过大的图片
+pub fn check(spec: &Spec, (width, height): (u32, u32)) -> Result<(), EngineError> {
+    let invalid = |reason: String| Err(EngineError::InvalidSpec(spec.to_string(), reason));
+    let too_large = |w: u32, h: u32| {
+        if w > MAX_DIMENSION || h > MAX_DIMENSION {
+            invalid(format!(
+                "result {}x{} is larger than {}x{}",
+                w, h, MAX_DIMENSION, MAX_DIMENSION
+            ))
+        } else {
+            Ok(())
+        }
+    };
+
+    match spec.data {
+        Some(spec::Data::Resize(ref v)) => {
+            if resize::ResizeType::from_i32(v.rtype).is_none()
+                || resize::SampleFilter::from_i32(v.filter).is_none()
+                || resize::Fit::from_i32(v.fit).is_none()
+                || Gravity::from_i32(v.gravity).is_none()
+            {
+                return invalid("unknown resize type, filter, fit or gravity".into());
+            }
+            if v.width == 0 || v.height == 0 {
+                return invalid("width and height must be positive".into());
+            }
+            too_large(v.width, v.height)?;
+            let (w, h) = smart::scaled_size((width, height), v);
+            too_large(w, h)
+        }
+        Some(spec::Data::Crop(ref v)) if v.width > 0 || v.height > 0 => {
+            if Gravity::from_i32(v.gravity).is_none() {
+                return invalid("unknown gravity".into());
+            }
+            if v.width == 0 || v.height == 0 {
+                return invalid("width and height must be positive".into());
+            }
+            if v.width > width || v.height > height {
+                return invalid(format!("crop size is larger than {}x{}", width, height));
+            }
+            Ok(())
+        }
+        Some(spec::Data::Crop(ref v)) => {
+            if v.x1 >= v.x2 || v.y1 >= v.y2 {
+                return invalid("crop area is empty".into());
+            }
+            if v.x2 > width || v.y2 > height {
+                return invalid(format!("crop area is outside of {}x{}", width, height));
+            }
+            Ok(())
+        }
+        Some(spec::Data::Contrast(ref v)) if !(-255.0..=255.0).contains(&v.contrast) => {
+            invalid("contrast must be in [-255, 255]".into())
+        }
+        Some(spec::Data::Filter(ref v)) if filter::Filter::from_i32(v.filter).is_none() => {
+            invalid("unknown filter".into())
+        }
+        Some(spec::Data::Watermark(ref v)) => {
+            if watermark::Anchor::from_i32(v.anchor).is_none() {
+                return invalid("unknown anchor".into());
+            }
+            if !(0.0..=1.0).contains(&v.scale) {
+                return invalid("scale must be in [0, 1]".into());
+            }
+            if !(0.0..=1.0).contains(&v.opacity_or_default()) {
+                return invalid("opacity must be in [0, 1]".into());
+            }
+            Ok(())
+        }
+        Some(spec::Data::Rotate(ref v)) if !v.angle.is_finite() => {
+            invalid("angle must be finite".into())
+        }
+        Some(spec::Data::Blur(ref v)) if v.radius > MAX_BLUR_RADIUS => {
+            invalid(format!("radius must be at most {}", MAX_BLUR_RADIUS))
+        }
+        Some(spec::Data::Brightness(ref v)) if !(-255..=255).contains(&v.brightness) => {
+            invalid("brightness must be in [-255, 255]".into())
+        }
+        Some(spec::Data::Padding(ref v)) => too_large(
+            width.saturating_add(v.left).saturating_add(v.right),
+            height.saturating_add(v.top).saturating_add(v.bottom),
+        ),
+        Some(spec::Data::Text(ref v)) => {
+            // 字号太大时栅格化很慢，最多和图片的最大尺寸一样大
+            if !(v.size > 0.0 && v.size <= MAX_DIMENSION as f32) {
+                return invalid(format!("font size must be in (0, {}]", MAX_DIMENSION));
+            }
+            if load_font(&v.font).is_none() {
+                return invalid(format!("cannot load font {:?}", v.font));
+            }
+            Ok(())
+        }
+        Some(spec::Data::Output(ref v)) => {
+            if output::Format::from_i32(v.format).is_none() {
+                return invalid("unknown format".into());
+            }
+            if output::Metadata::from_i32(v.metadata).is_none() {
+                return invalid("unknown metadata mode".into());
+            }
+            if v.quality > 100 {
+                return invalid("quality must be at most 100".into());
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+lazy_static! {
+    // 按名称缓存加载过的字体，加载失败的字体记录为 None
+    static ref FONTS: Mutex<HashMap<String, Option<Font<'static>>>> = Mutex::new(HashMap::new());
+    // 内置的 Roboto 字体，没有指定字体时使用
+    static ref DEFAULT_FONT: Font<'static> = {
+        let data = include_bytes!("../../fonts/Roboto-Regular.ttf");
+        Font::try_from_bytes(data).unwrap()
+    };
+    // 预先把内置的水印加载为静态变量
+    static ref DEFAULT_WATERMARK: RgbaImage = {
+        let data = include_bytes!("../../rust-logo.png");
+        let watermark = image::load_from_memory(data).unwrap().to_rgba8();
+        imageops::resize(&watermark, 64, 64, imageops::FilterType::Nearest)
+    };
+}
+
+// 以下是两个 Engine 共用的实现
+
+// 内存中对图片格式转换的方法 没有提供需要手动实现
+fn encode(rgba: RgbaImage, format: output::Format, quality: u8) -> Result<Vec<u8>, EngineError> {
+    // image 不支持 WebP 编码，使用 libwebp
+    if format == output::Format::Webp {
+        let (width, height) = rgba.dimensions();
+        let encoder = webp::Encoder::from_rgba(&rgba, width, height);
+        return Ok(encoder.encode(quality as f32).to_vec());
+    }
+
+    let format = match format {
+        output::Format::Png => ImageOutputFormat::Png,
+        output::Format::Gif => ImageOutputFormat::Gif,
+        _ => ImageOutputFormat::Jpeg(quality),
+    };
+    let dynimage = DynamicImage::ImageRgba8(rgba);
+
+    let mut buffer = Vec::with_capacity(32768);
+    dynimage
+        .write_to(&mut buffer, format)
+        .map_err(|e| EngineError::Encode(e.to_string()))?;
+    Ok(buffer)
+}
+
+// 旋转的角度不是 0 时返回旋转后的图片
+fn rotate(img: &RgbaImage, angle: f32) -> Option<RgbaImage> {
+    let angle = angle.rem_euclid(360.0);
+    let img = if angle == 0.0 {
+        return None;
+    } else if angle == 90.0 {
+        imageops::rotate90(img)
+    } else if angle == 180.0 {
+        imageops::rotate180(img)
+    } else if angle == 270.0 {
+        imageops::rotate270(img)
+    } else {
+        // 任意角度旋转时保持图片大小不变，空出来的部分是透明的
+        rotate_about_center(
+            img,
+            angle.to_radians(),
+            Interpolation::Bilinear,
+            Rgba([0, 0, 0, 0]),
+        )
+    };
+    Some(img)
+}
+
+fn pad(img: &RgbaImage, op: &Padding) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let mut canvas = RgbaImage::from_pixel(
+        width + op.left + op.right,
+        height + op.top + op.bottom,
+        to_color(op.color),
+    );
+    imageops::replace(&mut canvas, img, op.left, op.top);
+    canvas
+}
+
+// 按照 scale、opacity 和 anchor 把水印叠加到图片上
+fn watermark(img: &mut RgbaImage, mut mark: RgbaImage, op: &Watermark) {
+    let (width, height) = img.dimensions();
+    if op.scale > 0.0 {
+        let w = ((width as f32 * op.scale).round() as u32).max(1);
+        let h = ((mark.height() as u64 * w as u64 / mark.width().max(1) as u64) as u32).max(1);
+        mark = imageops::resize(&mark, w, h, imageops::FilterType::Triangle);
+    }
+
+    let opacity = op.opacity_or_default();
+    if opacity < 1.0 {
+        for p in mark.pixels_mut() {
+            p.0[3] = (p.0[3] as f32 * opacity).round() as u8;
+        }
+    }
+
+    // 水印比图片大时从左上角开始放
+    let (w, h) = mark.dimensions();
+    let right = width.saturating_sub(w).saturating_sub(op.x);
+    let bottom = height.saturating_sub(h).saturating_sub(op.y);
+    let (x, y) = match op.anchor() {
+        watermark::Anchor::TopLeft => (op.x, op.y),
+        watermark::Anchor::TopRight => (right, op.y),
+        watermark::Anchor::BottomLeft => (op.x, bottom),
+        watermark::Anchor::BottomRight => (right, bottom),
+        watermark::Anchor::Center => (
+            (width.saturating_sub(w) / 2).saturating_add(op.x),
+            (height.saturating_sub(h) / 2).saturating_add(op.y),
+        ),
+    };
+    imageops::overlay(img, &mark, x, y);
+}
+
+fn draw_text(img: &mut RgbaImage, op: &Text) -> Result<(), EngineError> {
+    // check 时已经加载过字体，这里不会失败
+    let font = load_font(&op.font)
+        .ok_or_else(|| EngineError::Internal(format!("font {:?} is not loaded", op.font)))?;
+
+    let scale = Scale::uniform(op.size);
+    draw_text_mut(img, to_color(op.color), op.x, op.y, scale, &font, &op.text);
+    Ok(())
+}
+
+// 颜色的格式为 0xRRGGBBAA
+fn to_color(color: u32) -> Rgba<u8> {
+    Rgba(color.to_be_bytes())
+}
+
+// 没有指定字体时使用内置的字体，否则从字体目录加载 <name>.ttf，
+// 字体目录可以通过 THUMBOR_FONT_DIR 设置，默认为 fonts
+fn load_font(name: &str) -> Option<Font<'static>> {
+    if name.is_empty() {
+        return Some(DEFAULT_FONT.clone());
+    }
+    // 只允许简单的名字，避免读取字体目录之外的文件
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        warn!("Invalid font name: {}", name);
+        return None;
+    }
+
+    let mut fonts = FONTS.lock().unwrap();
+    fonts
+        .entry(name.to_string())
+        .or_insert_with(|| {
+            let dir = env::var("THUMBOR_FONT_DIR").unwrap_or_else(|_| "fonts".into());
+            let path = Path::new(&dir).join(format!("{}.ttf", name));
+            let font = fs::read(&path).ok().and_then(Font::try_from_vec);
+            if font.is_none() {
+                warn!("Failed to load font {:?}", path);
+            }
+            font
+        })
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::*;
+    use image::GenericImageView;
+
+    // 200x100 的渐变图片
+    fn source() -> Bytes {
+        let img = RgbaImage::from_fn(200, 100, |x, y| Rgba([x as u8, y as u8, 128, 255]));
+        encode(img, output::Format::Png, 100).unwrap().into()
+    }
+
+    // 10x10 的红色方块
+    fn red_square() -> Bytes {
+        let img = RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255]));
+        encode(img, output::Format::Png, 100).unwrap().into()
+    }
+
+    fn all_specs() -> Vec<Spec> {
+        let mut specs = vec![
+            Spec::new_resize(100, 80, resize::SampleFilter::CatmullRom),
+            Spec::new_resize(300, 150, resize::SampleFilter::Undefined),
+            Spec::new_resize_seam_carve(180, 90),
+            Spec::new_crop(10, 20, 110, 70),
+            Spec::new_flipv(),
+            Spec::new_fliph(),
+            Spec {
+                data: Some(spec::Data::Contrast(Contrast { contrast: 30.0 })),
+            },
+            Spec::new_watermark(20, 20),
+            Spec {
+                data: Some(spec::Data::Watermark(Watermark {
+                    x: 5,
+                    y: 5,
+                    name: "red".into(),
+                    scale: 0.5,
+                    opacity: Some(0.5),
+                    anchor: watermark::Anchor::BottomRight as _,
+                    ..Default::default()
+                })),
+            },
+            Spec::new_rotate(90.0),
+            Spec::new_rotate(45.0),
+            Spec::new_blur(3),
+            Spec::new_sharpen(),
+            Spec::new_brightness(40),
+            Spec::new_brightness(-40),
+            Spec::new_grayscale(),
+            Spec::new_sepia(),
+            Spec::new_padding(10, 20, 30, 40, 0xff0000ff),
+            Spec::new_text("hello", 10, 10, "", 24.0, 0x000000ff),
+            Spec::new_output(output::Format::Png, 0),
+            Spec::new_resize_fit(120, 120, resize::Fit::Cover, Gravity::Entropy),
+            Spec::new_resize_fit(120, 120, resize::Fit::Contain, Gravity::Center),
+            Spec::new_resize_fit(120, 120, resize::Fit::Inside, Gravity::Center),
+            Spec::new_resize_fit(150, 100, resize::Fit::Outside, Gravity::Center),
+            Spec::new_smart_crop(80, 80, Gravity::Edge),
+        ];
+        specs.extend(filter::Filter::all().map(Spec::new_filter));
+        specs
+    }
+
+    fn dimensions(kind: EngineKind, specs: &[Spec]) -> (u32, u32) {
+        let output = Output {
+            format: output::Format::Png as _,
+            ..Default::default()
+        };
+        let mut watermarks = Watermarks::default();
+        watermarks.insert(WatermarkSource::Name("red".into()), red_square());
+        let data = kind.process(source(), specs, &watermarks, &output).unwrap();
+        let img = image::load_from_memory(&data).unwrap();
+        (img.width(), img.height())
+    }
+
+    #[test]
+    fn engines_should_produce_same_dimensions() {
+        for spec in all_specs() {
+            let specs = [spec];
+            assert_eq!(
+                dimensions(EngineKind::Photon, &specs),
+                dimensions(EngineKind::ImageRs, &specs),
+                "{}",
+                specs[0]
+            );
+        }
+
+        // 所有的 spec 依次执行
+        let specs = all_specs();
+        assert_eq!(
+            dimensions(EngineKind::Photon, &specs),
+            dimensions(EngineKind::ImageRs, &specs)
+        );
+    }
+
+    #[test]
+    fn invalid_specs_should_be_rejected() {
+        let output = Output::default();
+        let watermarks = Watermarks::default();
+        let invalid = [
+            Spec::new_resize(0, 100, resize::SampleFilter::Nearest),
+            Spec::new_resize(MAX_DIMENSION + 1, 100, resize::SampleFilter::Nearest),
+            Spec::new_crop(100, 0, 300, 50),
+            Spec::new_smart_crop(300, 50, Gravity::Entropy),
+            Spec::new_resize_fit(10, MAX_DIMENSION, resize::Fit::Outside, Gravity::Center),
+            Spec::new_padding(0, MAX_DIMENSION, 0, 0, 0),
+            Spec::new_blur(MAX_BLUR_RADIUS + 1),
+            Spec::new_output(output::Format::Jpeg, 101),
+            Spec::new_text("hello", 10, 10, "missing-font", 24.0, 0x000000ff),
+            Spec::new_text("hello", 10, 10, "../font", 24.0, 0x000000ff),
+            Spec::new_text("hello", 10, 10, "", MAX_DIMENSION as f32 + 1.0, 0x000000ff),
+            Spec::new_text("hello", 10, 10, "", f32::INFINITY, 0x000000ff),
+            Spec {
+                data: Some(spec::Data::Watermark(Watermark {
+                    opacity: Some(2.0),
+                    ..Default::default()
+                })),
+            },
+        ];
+        for kind in [EngineKind::Photon, EngineKind::ImageRs] {
+            for spec in invalid.iter() {
+                let err = kind
+                    .process(source(), std::slice::from_ref(spec), &watermarks, &output)
+                    .unwrap_err();
+                assert!(matches!(err, EngineError::InvalidSpec(..)), "{}", spec);
+                assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+            }
+
+            // 按照前面 spec 处理后的尺寸检查
+            let specs = [
+                Spec::new_resize(50, 50, resize::SampleFilter::Nearest),
+                Spec::new_crop(0, 0, 100, 50),
+            ];
+            assert!(kind
+                .process(source(), &specs, &watermarks, &output)
+                .is_err());
+
+            let err = kind
+                .process(Bytes::from("not an image"), &[], &watermarks, &output)
+                .unwrap_err();
+            assert!(matches!(err, EngineError::Decode(_)));
+
+            // 水印需要事先准备好
+            let spec = Spec {
+                data: Some(spec::Data::Watermark(Watermark {
+                    url: "https://example.com/logo.png".into(),
+                    ..Default::default()
+                })),
+            };
+            let err = kind
+                .process(source(), &[spec], &watermarks, &output)
+                .unwrap_err();
+            assert!(matches!(err, EngineError::Internal(_)));
+        }
+    }
+
+    #[test]
+    fn watermark_should_be_placed_at_anchor() {
+        let mark = RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255]));
+        let cases = [
+            (watermark::Anchor::TopLeft, (5, 5)),
+            (watermark::Anchor::TopRight, (85, 5)),
+            (watermark::Anchor::BottomLeft, (5, 85)),
+            (watermark::Anchor::BottomRight, (85, 85)),
+            (watermark::Anchor::Center, (50, 50)),
+        ];
+        for (anchor, (x, y)) in cases {
+            let mut img = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
+            let op = Watermark {
+                x: 5,
+                y: 5,
+                anchor: anchor as _,
+                ..Default::default()
+            };
+            watermark(&mut img, mark.clone(), &op);
+            assert_eq!(img.get_pixel(x, y), &Rgba([255, 0, 0, 255]), "{:?}", anchor);
+            assert_eq!(img.get_pixel(x + 9, y + 9), &Rgba([255, 0, 0, 255]));
+            assert_eq!(img.get_pixel(x + 10, y + 10), &Rgba([255, 255, 255, 255]));
+        }
+
+        // 按图片宽度缩放，同时保持水印的宽高比
+        let mut img = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
+        let op = Watermark {
+            scale: 0.5,
+            opacity: Some(0.5),
+            ..Default::default()
+        };
+        watermark(&mut img, mark.clone(), &op);
+        assert_ne!(img.get_pixel(49, 49), &Rgba([255, 255, 255, 255]));
+        assert_eq!(img.get_pixel(50, 50), &Rgba([255, 255, 255, 255]));
+        assert_ne!(img.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+
+        // 不透明度为 0 时水印完全透明
+        let origin = RgbaImage::from_pixel(100, 100, Rgba([255, 255, 255, 255]));
+        let mut img = origin.clone();
+        let op = Watermark {
+            opacity: Some(0.0),
+            ..Default::default()
+        };
+        watermark(&mut img, mark, &op);
+        assert_eq!(img, origin);
+    }
+
+    #[test]
+    fn text_should_be_drawn_with_default_font() {
+        let white = Rgba([255, 255, 255, 255]);
+        let op = Text {
+            text: "hello".into(),
+            x: 10,
+            y: 10,
+            size: 24.0,
+            color: 0xff0000ff,
+            ..Default::default()
+        };
+        let mut img = RgbaImage::from_pixel(100, 50, white);
+        draw_text(&mut img, &op).unwrap();
+        assert!(img.pixels().any(|p| p != &white));
+        // 文字在 (x, y) 的右下方
+        assert!(img
+            .enumerate_pixels()
+            .all(|(x, y, p)| p == &white || (x >= 10 && y >= 10)));
+
+        // 两个 Engine 的结果一致
+        let output = Output {
+            format: output::Format::Png as _,
+            ..Default::default()
+        };
+        let specs = [Spec::new_text("hello", 10, 10, "", 24.0, 0xff0000ff)];
+        let results: Vec<_> = [EngineKind::Photon, EngineKind::ImageRs]
+            .into_iter()
+            .map(|kind| {
+                let data = kind
+                    .process(source(), &specs, &Watermarks::default(), &output)
+                    .unwrap();
+                image::load_from_memory(&data).unwrap().to_rgba8()
+            })
+            .collect();
+        let origin = image::load_from_memory(&source()).unwrap().to_rgba8();
+        assert_ne!(results[0], origin);
+        assert_eq!(results[0], results[1]);
+    }
+
+    #[test]
+    fn engine_kind_should_be_parsed() {
+        assert_eq!("photon".parse::<EngineKind>().unwrap(), EngineKind::Photon);
+        assert_eq!("image".parse::<EngineKind>().unwrap(), EngineKind::ImageRs);
+        assert!("gpu".parse::<EngineKind>().is_err());
+    }
 }
@@ -1,124 +1,234 @@
-use super::{Engine, SpecTransform};
+use super::{
+    check, draw_text, encode, pad, rotate,
+    smart::{find_region, fit, scaled_size},
+    watermark, Engine, EngineError, SpecTransform, Watermarks,
+};
 use crate::pb::*;
-use anyhow::Result;
 use bytes::Bytes;
-use image::{DynamicImage, ImageBuffer, ImageOutputFormat};
-use lazy_static::lazy_static;
+use image::{ImageBuffer, RgbaImage};
 use photon_rs::{
-    effects, filters, multiple, native::open_image_from_bytes, transform, PhotonImage,
+    conv, effects, filters, monochrome, native::open_image_from_bytes, transform, PhotonImage,
 };
-
-lazy_static! {
-    // 预先把水印加载为静态变量
-    static ref WATERMARK: PhotonImage = {
-        let data = include_bytes!("../../rust-logo.png");
-        let watermark = open_image_from_bytes(data).unwrap();
-        transform::resize(&watermark, 64, 64, transform::SamplingFilter::Nearest)
-    };
-}
+use std::cmp::Ordering;
 
 pub struct Photon(PhotonImage);
 
 impl TryFrom<Bytes> for Photon {
-    type Error = anyhow::Error;
+    type Error = EngineError;
 
     fn try_from(data: Bytes) -> Result<Self, Self::Error> {
-        Ok(Self(open_image_from_bytes(&data)?))
+        let img = open_image_from_bytes(&data).map_err(|e| EngineError::Decode(e.to_string()))?;
+        Ok(Self(img))
     }
 }
 
 impl Engine for Photon {
-    fn apply(&mut self, specs: &[Spec]) {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.0.get_width(), self.0.get_height())
+    }
+
+    fn apply(&mut self, specs: &[Spec], watermarks: &Watermarks) -> Result<(), EngineError> {
         for spec in specs.iter() {
+            check(spec, self.dimensions())?;
             match spec.data {
-                Some(spec::Data::Crop(ref v)) => self.transform(v),
-                Some(spec::Data::Contrast(ref v)) => self.transform(v),
-                Some(spec::Data::Filter(ref v)) => self.transform(v),
-                Some(spec::Data::Fliph(ref v)) => self.transform(v),
-                Some(spec::Data::Flipv(ref v)) => self.transform(v),
-                Some(spec::Data::Resize(ref v)) => self.transform(v),
-                Some(spec::Data::Watermark(ref v)) => self.transform(v),
+                Some(spec::Data::Crop(ref v)) => self.transform(v)?,
+                Some(spec::Data::Contrast(ref v)) => self.transform(v)?,
+                Some(spec::Data::Filter(ref v)) => self.transform(v)?,
+                Some(spec::Data::Fliph(ref v)) => self.transform(v)?,
+                Some(spec::Data::Flipv(ref v)) => self.transform(v)?,
+                Some(spec::Data::Resize(ref v)) => self.transform(v)?,
+                Some(spec::Data::Watermark(ref v)) => self.transform((v, watermarks.image(v)?))?,
+                Some(spec::Data::Rotate(ref v)) => self.transform(v)?,
+                Some(spec::Data::Blur(ref v)) => self.transform(v)?,
+                Some(spec::Data::Sharpen(ref v)) => self.transform(v)?,
+                Some(spec::Data::Brightness(ref v)) => self.transform(v)?,
+                Some(spec::Data::Grayscale(ref v)) => self.transform(v)?,
+                Some(spec::Data::Sepia(ref v)) => self.transform(v)?,
+                Some(spec::Data::Padding(ref v)) => self.transform(v)?,
+                Some(spec::Data::Text(ref v)) => self.transform(v)?,
+                // 输出格式在 generate 时处理
+                Some(spec::Data::Output(_)) => {}
 
                 // 对于目前不支持的spec 不做任何处理
                 _ => {}
             }
         }
+        Ok(())
     }
 
-    fn generate(self, format: ImageOutputFormat) -> Vec<u8> {
-        image_to_buf(self.0, format)
+    fn generate(self, output: &Output) -> Result<Vec<u8>, EngineError> {
+        encode(
+            to_rgba(&self.0)?,
+            output.format(),
+            output.quality_or_default(),
+        )
     }
 }
 
-// 内存中对图片格式转换的方法 没有提供需要手动实现
-fn image_to_buf(img: PhotonImage, format: ImageOutputFormat) -> Vec<u8> {
-    let raw_pixels = img.get_raw_pixels();
-    let width = img.get_width();
-    let height = img.get_height();
-
-    let img_buffer = ImageBuffer::from_vec(width, height, raw_pixels).unwrap();
-    let dynimage = DynamicImage::ImageRgba8(img_buffer);
-
-    let mut buffer = Vec::with_capacity(32768);
-    dynimage.write_to(&mut buffer, format).unwrap();
-    buffer
-}
-
 impl SpecTransform<&Crop> for Photon {
-    fn transform(&mut self, op: &Crop) {
-        let img = transform::crop(&mut self.0, op.x1, op.y1, op.x2, op.y2);
+    fn transform(&mut self, op: &Crop) -> Result<(), EngineError> {
+        let (x1, y1, x2, y2) = match (op.width, op.height) {
+            (0, 0) => (op.x1, op.y1, op.x2, op.y2),
+            (w, h) => {
+                let (x, y) = find_region(&to_rgba(&self.0)?, w, h, op.gravity());
+                (x, y, x + w, y + h)
+            }
+        };
+        let img = transform::crop(&mut self.0, x1, y1, x2, y2);
         self.0 = img;
+        Ok(())
     }
 }
 
 impl SpecTransform<&Contrast> for Photon {
-    fn transform(&mut self, op: &Contrast) {
+    fn transform(&mut self, op: &Contrast) -> Result<(), EngineError> {
         effects::adjust_contrast(&mut self.0, op.contrast);
+        Ok(())
     }
 }
 
 impl SpecTransform<&Flipv> for Photon {
-    fn transform(&mut self, _op: &Flipv) {
-        transform::flipv(&mut self.0)
+    fn transform(&mut self, _op: &Flipv) -> Result<(), EngineError> {
+        transform::flipv(&mut self.0);
+        Ok(())
     }
 }
 
 impl SpecTransform<&Fliph> for Photon {
-    fn transform(&mut self, _op: &Fliph) {
-        transform::fliph(&mut self.0)
+    fn transform(&mut self, _op: &Fliph) -> Result<(), EngineError> {
+        transform::fliph(&mut self.0);
+        Ok(())
     }
 }
 
 impl SpecTransform<&Filter> for Photon {
-    fn transform(&mut self, op: &Filter) {
+    fn transform(&mut self, op: &Filter) -> Result<(), EngineError> {
         match filter::Filter::from_i32(op.filter) {
             Some(filter::Filter::Unspecified) => {}
+            Some(filter::Filter::Golden) => filters::golden(&mut self.0),
+            Some(filter::Filter::Lofi) => filters::lofi(&mut self.0),
+            Some(filter::Filter::PastelPink) => filters::pastel_pink(&mut self.0),
+            Some(filter::Filter::Cali) => filters::cali(&mut self.0),
+            Some(filter::Filter::Dramatic) => filters::dramatic(&mut self.0),
+            Some(filter::Filter::Firenze) => filters::firenze(&mut self.0),
+            Some(filter::Filter::Obsidian) => filters::obsidian(&mut self.0),
             Some(f) => filters::filter(&mut self.0, f.to_str().unwrap()),
             _ => {}
         }
+        Ok(())
     }
 }
 
 impl SpecTransform<&Resize> for Photon {
-    fn transform(&mut self, op: &Resize) {
-        let img = match resize::ResizeType::from_i32(op.rtype).unwrap() {
-            resize::ResizeType::Normal => transform::resize(
-                &mut self.0,
-                op.width,
-                op.height,
-                resize::SampleFilter::from_i32(op.filter).unwrap().into(),
-            ),
-            resize::ResizeType::SeamCarve => {
-                transform::seam_carve(&mut self.0, op.width, op.height)
+    fn transform(&mut self, op: &Resize) -> Result<(), EngineError> {
+        let (width, height) = scaled_size(self.dimensions(), op);
+        let img = match op.rtype() {
+            resize::ResizeType::Normal => {
+                transform::resize(&mut self.0, width, height, op.filter().into())
             }
+            resize::ResizeType::SeamCarve => transform::seam_carve(&mut self.0, width, height),
         };
 
         self.0 = img;
+        // COVER 和 CONTAIN 还需要裁剪或者填充到目标大小
+        if matches!(op.fit(), resize::Fit::Cover | resize::Fit::Contain) {
+            self.0 = from_rgba(fit(to_rgba(&self.0)?, op));
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<(&Watermark, RgbaImage)> for Photon {
+    fn transform(&mut self, (op, mark): (&Watermark, RgbaImage)) -> Result<(), EngineError> {
+        let mut img = to_rgba(&self.0)?;
+        watermark(&mut img, mark, op);
+        self.0 = from_rgba(img);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Rotate> for Photon {
+    fn transform(&mut self, op: &Rotate) -> Result<(), EngineError> {
+        if let Some(img) = rotate(&to_rgba(&self.0)?, op.angle) {
+            self.0 = from_rgba(img);
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Blur> for Photon {
+    fn transform(&mut self, op: &Blur) -> Result<(), EngineError> {
+        conv::gaussian_blur(&mut self.0, op.radius as i32);
+        Ok(())
     }
 }
 
-impl SpecTransform<&Watermark> for Photon {
-    fn transform(&mut self, op: &Watermark) {
-        multiple::watermark(&mut self.0, &WATERMARK, op.x, op.y);
+impl SpecTransform<&Sharpen> for Photon {
+    fn transform(&mut self, _op: &Sharpen) -> Result<(), EngineError> {
+        conv::sharpen(&mut self.0);
+        Ok(())
     }
 }
+
+impl SpecTransform<&Brightness> for Photon {
+    fn transform(&mut self, op: &Brightness) -> Result<(), EngineError> {
+        // check 已经保证了 brightness 在 [-255, 255] 之间
+        let brightness = op.brightness;
+        match brightness.cmp(&0) {
+            Ordering::Greater => effects::inc_brightness(&mut self.0, brightness as u8),
+            // photon 没有降低亮度的方法，直接处理 RGB 通道，不修改 alpha
+            Ordering::Less => {
+                let delta = brightness.unsigned_abs() as u8;
+                let mut pixels = self.0.get_raw_pixels();
+                for p in pixels.chunks_exact_mut(4) {
+                    for v in &mut p[..3] {
+                        *v = v.saturating_sub(delta);
+                    }
+                }
+                self.0 = PhotonImage::new(pixels, self.0.get_width(), self.0.get_height());
+            }
+            Ordering::Equal => {}
+        }
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Grayscale> for Photon {
+    fn transform(&mut self, _op: &Grayscale) -> Result<(), EngineError> {
+        monochrome::grayscale(&mut self.0);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Sepia> for Photon {
+    fn transform(&mut self, _op: &Sepia) -> Result<(), EngineError> {
+        monochrome::sepia(&mut self.0);
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Padding> for Photon {
+    fn transform(&mut self, op: &Padding) -> Result<(), EngineError> {
+        self.0 = from_rgba(pad(&to_rgba(&self.0)?, op));
+        Ok(())
+    }
+}
+
+impl SpecTransform<&Text> for Photon {
+    fn transform(&mut self, op: &Text) -> Result<(), EngineError> {
+        let mut img = to_rgba(&self.0)?;
+        draw_text(&mut img, op)?;
+        self.0 = from_rgba(img);
+        Ok(())
+    }
+}
+
+fn to_rgba(img: &PhotonImage) -> Result<RgbaImage, EngineError> {
+    ImageBuffer::from_vec(img.get_width(), img.get_height(), img.get_raw_pixels())
+        .ok_or_else(|| EngineError::Internal("pixels do not match image size".into()))
+}
+
+fn from_rgba(img: RgbaImage) -> PhotonImage {
+    let (width, height) = img.dimensions();
+    PhotonImage::new(img.into_raw(), width, height)
+}
@@ -0,0 +1,177 @@
+use crate::pb::{resize, Gravity, Resize};
+use image::{imageops, GrayImage, Rgba, RgbaImage};
+
+// 智能裁剪时每次最多去掉的像素数
+const STEP: u32 = 8;
+
+/// 按 fit 计算缩放后的大小，COVER 和 CONTAIN 之后还需要 fit 处理
+pub fn scaled_size((width, height): (u32, u32), op: &Resize) -> (u32, u32) {
+    let sx = op.width as f64 / width.max(1) as f64;
+    let sy = op.height as f64 / height.max(1) as f64;
+    let scale = match op.fit() {
+        resize::Fit::Fill => return (op.width, op.height),
+        resize::Fit::Contain | resize::Fit::Inside => sx.min(sy),
+        resize::Fit::Cover | resize::Fit::Outside => sx.max(sy),
+    };
+    let w = ((width as f64 * scale).round() as u32).max(1);
+    let h = ((height as f64 * scale).round() as u32).max(1);
+    // 避免舍入误差导致 COVER 比目标小或者 CONTAIN 比目标大
+    match op.fit() {
+        resize::Fit::Cover => (w.max(op.width), h.max(op.height)),
+        resize::Fit::Contain => (w.min(op.width), h.min(op.height)),
+        _ => (w, h),
+    }
+}
+
+/// COVER 按 gravity 裁掉多余的部分，CONTAIN 居中并用透明填充到目标大小
+pub fn fit(img: RgbaImage, op: &Resize) -> RgbaImage {
+    match op.fit() {
+        resize::Fit::Cover => {
+            let (x, y) = find_region(&img, op.width, op.height, op.gravity());
+            imageops::crop_imm(&img, x, y, op.width, op.height).to_image()
+        }
+        resize::Fit::Contain => {
+            let mut canvas = RgbaImage::from_pixel(op.width, op.height, Rgba([0, 0, 0, 0]));
+            let x = (op.width - img.width()) / 2;
+            let y = (op.height - img.height()) / 2;
+            imageops::replace(&mut canvas, &img, x, y);
+            canvas
+        }
+        _ => img,
+    }
+}
+
+/// 在图片中选择 width x height 的区域，返回左上角的位置
+/// ENTROPY 和 EDGE 每次比较两端的一条，去掉得分低的那条，直到剩下需要的大小
+pub fn find_region(img: &RgbaImage, width: u32, height: u32, gravity: Gravity) -> (u32, u32) {
+    let (w, h) = img.dimensions();
+    let (width, height) = (width.min(w), height.min(h));
+    if gravity == Gravity::Center {
+        return ((w - width) / 2, (h - height) / 2);
+    }
+
+    let luma = imageops::grayscale(img);
+    let map = match gravity {
+        Gravity::Edge => edges(&luma),
+        _ => luma,
+    };
+    let score = |x: u32, y: u32, w: u32, h: u32| match gravity {
+        Gravity::Edge => sum(&map, x, y, w, h),
+        _ => entropy(&map, x, y, w, h),
+    };
+
+    let (mut left, mut right) = (0, w);
+    while right - left > width {
+        let step = (right - left - width).min(STEP);
+        if score(left, 0, step, h) < score(right - step, 0, step, h) {
+            left += step;
+        } else {
+            right -= step;
+        }
+    }
+
+    let (mut top, mut bottom) = (0, h);
+    while bottom - top > height {
+        let step = (bottom - top - height).min(STEP);
+        if score(left, top, width, step) < score(left, bottom - step, width, step) {
+            top += step;
+        } else {
+            bottom -= step;
+        }
+    }
+    (left, top)
+}
+
+// 每个像素和右边、下边像素的亮度差
+fn edges(luma: &GrayImage) -> GrayImage {
+    let (w, h) = luma.dimensions();
+    GrayImage::from_fn(w, h, |x, y| {
+        let v = luma.get_pixel(x, y).0[0] as i32;
+        let right = luma.get_pixel((x + 1).min(w - 1), y).0[0] as i32;
+        let below = luma.get_pixel(x, (y + 1).min(h - 1)).0[0] as i32;
+        let d = (v - right).abs() + (v - below).abs();
+        image::Luma([d.min(255) as u8])
+    })
+}
+
+fn sum(map: &GrayImage, x: u32, y: u32, w: u32, h: u32) -> f64 {
+    let mut total = 0u64;
+    for j in y..y + h {
+        for i in x..x + w {
+            total += map.get_pixel(i, j).0[0] as u64;
+        }
+    }
+    total as f64
+}
+
+// 亮度直方图的信息熵
+fn entropy(map: &GrayImage, x: u32, y: u32, w: u32, h: u32) -> f64 {
+    let mut histogram = [0u32; 256];
+    for j in y..y + h {
+        for i in x..x + w {
+            histogram[map.get_pixel(i, j).0[0] as usize] += 1;
+        }
+    }
+    let total = (w * h) as f64;
+    histogram
+        .iter()
+        .filter(|&&n| n > 0)
+        .map(|&n| {
+            let p = n as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resize(width: u32, height: u32, fit: resize::Fit) -> Resize {
+        Resize {
+            width,
+            height,
+            fit: fit as i32,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scaled_size_should_follow_fit() {
+        let cases = [
+            (resize::Fit::Fill, (100, 100)),
+            (resize::Fit::Cover, (200, 100)),
+            (resize::Fit::Contain, (100, 50)),
+            (resize::Fit::Inside, (100, 50)),
+            (resize::Fit::Outside, (200, 100)),
+        ];
+        for (fit, expected) in cases {
+            let op = resize(100, 100, fit);
+            assert_eq!(scaled_size((400, 200), &op), expected, "{:?}", fit);
+        }
+
+        // fit 之后 COVER 和 CONTAIN 都是目标大小
+        let img = RgbaImage::new(200, 100);
+        let op = resize(100, 100, resize::Fit::Cover);
+        assert_eq!(fit(img, &op).dimensions(), (100, 100));
+        let img = RgbaImage::new(100, 50);
+        let op = resize(100, 100, resize::Fit::Contain);
+        assert_eq!(fit(img, &op).dimensions(), (100, 100));
+    }
+
+    #[test]
+    fn smart_crop_should_find_busy_region() {
+        // 只有 80..120 是棋盘格，其余是纯色
+        let img = RgbaImage::from_fn(200, 100, |x, y| match (80..120).contains(&x) {
+            true if (x / 2 + y / 2) % 2 == 0 => Rgba([255, 255, 255, 255]),
+            true => Rgba([0, 0, 0, 255]),
+            false => Rgba([128, 128, 128, 255]),
+        });
+        assert_eq!(find_region(&img, 50, 100, Gravity::Center), (75, 0));
+        for gravity in [Gravity::Entropy, Gravity::Edge] {
+            let (x, y) = find_region(&img, 50, 100, gravity);
+            assert!((70..=80).contains(&x), "{:?}: {}", gravity, x);
+            assert_eq!(y, 0);
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use crate::config::{env_list, env_or};
+use axum::http::{header, StatusCode};
+use bytes::{Bytes, BytesMut};
+use reqwest::{redirect::Policy, Client, Response, Url};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::net::lookup_host;
+use tracing::info;
+
+/// 抓取原图时的错误，每种错误对应不同的 HTTP 状态码
+#[derive(Error, Debug, Clone)]
+pub enum FetchError {
+    #[error("Invalid url: {0}")]
+    InvalidUrl(String),
+    #[error("Url is not allowed: {0}")]
+    NotAllowed(String),
+    #[error("Host {0} resolves to non-public address {1}")]
+    PrivateAddress(String, IpAddr),
+    #[error("Cannot resolve host {0}")]
+    Unresolvable(String),
+    #[error("Too many redirects, at most {0} allowed")]
+    TooManyRedirects(usize),
+    #[error("Image is larger than {0} bytes")]
+    TooLarge(usize),
+    #[error("Fetching image timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Upstream error: {0}")]
+    Upstream(String),
+}
+
+impl FetchError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidUrl(_) => StatusCode::BAD_REQUEST,
+            Self::NotAllowed(_) | Self::PrivateAddress(..) => StatusCode::FORBIDDEN,
+            Self::Unresolvable(_) | Self::Upstream(_) => StatusCode::BAD_GATEWAY,
+            Self::TooManyRedirects(_) => StatusCode::LOOP_DETECTED,
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Upstream(e.to_string())
+    }
+}
+
+/// 抓取原图的限制
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    /// 允许的 scheme
+    pub schemes: Vec<String>,
+    /// 允许的 host，支持 *.example.com 的写法，为空时允许所有公网地址
+    pub hosts: Vec<String>,
+    /// 最多跟随的重定向次数
+    pub max_redirects: usize,
+    /// 原图最大的字节数
+    pub max_size: usize,
+    /// 整个抓取过程的超时时间，包括 DNS 解析和重定向
+    pub timeout: Duration,
+}
+
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            schemes: vec!["http".into(), "https".into()],
+            hosts: vec![],
+            max_redirects: 5,
+            max_size: 20 * 1024 * 1024,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl FetchConfig {
+    /// 从环境变量读取配置，没有设置的使用默认值
+    pub fn from_env() -> anyhow::Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            schemes: env_list("THUMBOR_ALLOWED_SCHEMES").unwrap_or(default.schemes),
+            hosts: env_list("THUMBOR_ALLOWED_HOSTS").unwrap_or(default.hosts),
+            max_redirects: env_or("THUMBOR_MAX_REDIRECTS", default.max_redirects)?,
+            max_size: env_or("THUMBOR_MAX_SIZE", default.max_size)?,
+            timeout: Duration::from_secs(env_or(
+                "THUMBOR_FETCH_TIMEOUT",
+                default.timeout.as_secs(),
+            )?),
+        })
+    }
+
+    // 检查 scheme 和 host 是否在允许的范围内
+    fn allows(&self, url: &Url) -> Result<(), FetchError> {
+        if !self.schemes.iter().any(|s| s == url.scheme()) {
+            return Err(FetchError::NotAllowed(format!("scheme {}", url.scheme())));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| FetchError::InvalidUrl(format!("{} has no host", url)))?;
+        if !self.hosts.is_empty() && !self.hosts.iter().any(|h| host_matches(h, host)) {
+            return Err(FetchError::NotAllowed(format!("host {}", host)));
+        }
+        Ok(())
+    }
+}
+
+/// 按照 FetchConfig 的限制抓取原图
+#[derive(Debug, Clone)]
+pub struct Fetcher {
+    config: Arc<FetchConfig>,
+}
+
+impl Fetcher {
+    pub fn new(config: FetchConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+
+    pub async fn fetch(&self, url: &str) -> Result<Bytes, FetchError> {
+        let timeout = self.config.timeout;
+        tokio::time::timeout(timeout, self.fetch_inner(url))
+            .await
+            .map_err(|_| FetchError::Timeout(timeout))?
+    }
+
+    async fn fetch_inner(&self, url: &str) -> Result<Bytes, FetchError> {
+        let mut url = Url::parse(url).map_err(|e| FetchError::InvalidUrl(e.to_string()))?;
+
+        // 自己处理重定向，每一跳都要做同样的检查
+        for _ in 0..=self.config.max_redirects {
+            let host = self.check(&url).await?;
+            let mut builder = Client::builder().redirect(Policy::none());
+            // 连接检查过的地址，避免再次解析时得到不同的结果（DNS rebinding）
+            if let Some((domain, addr)) = host {
+                builder = builder.resolve(&domain, addr);
+            }
+            let resp = builder.build()?.get(url.clone()).send().await?;
+
+            let status = resp.status();
+            if status.is_redirection() {
+                let location = resp
+                    .headers()
+                    .get(header::LOCATION)
+                    .and_then(|v| v.to_str().ok())
+                    .ok_or_else(|| FetchError::Upstream(format!("{} without location", status)))?;
+                url = url
+                    .join(location)
+                    .map_err(|e| FetchError::InvalidUrl(e.to_string()))?;
+                info!("Redirect to {}", url);
+                continue;
+            }
+            if !status.is_success() {
+                return Err(FetchError::Upstream(format!("{} returns {}", url, status)));
+            }
+            return self.read_body(resp).await;
+        }
+
+        Err(FetchError::TooManyRedirects(self.config.max_redirects))
+    }
+
+    // 检查 url 是否允许访问，host 为域名时返回解析得到的地址
+    async fn check(&self, url: &Url) -> Result<Option<(String, SocketAddr)>, FetchError> {
+        self.config.allows(url)?;
+        let host = url.host_str().unwrap_or_default();
+
+        // IPv6 的 host 带有 []
+        let ip = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = ip.parse::<IpAddr>() {
+            return match is_public(ip) {
+                true => Ok(None),
+                false => Err(FetchError::PrivateAddress(host.into(), ip)),
+            };
+        }
+
+        let port = url.port_or_known_default().unwrap_or(80);
+        let addrs: Vec<_> = lookup_host((host, port))
+            .await
+            .map_err(|_| FetchError::Unresolvable(host.into()))?
+            .collect();
+        // 任何一个地址不是公网地址都拒绝
+        if let Some(addr) = addrs.iter().find(|addr| !is_public(addr.ip())) {
+            return Err(FetchError::PrivateAddress(host.into(), addr.ip()));
+        }
+        match addrs.first() {
+            Some(addr) => Ok(Some((host.into(), *addr))),
+            None => Err(FetchError::Unresolvable(host.into())),
+        }
+    }
+
+    // 边读边检查大小，不相信 Content-Length
+    async fn read_body(&self, mut resp: Response) -> Result<Bytes, FetchError> {
+        let max = self.config.max_size;
+        if resp.content_length().map_or(false, |len| len > max as u64) {
+            return Err(FetchError::TooLarge(max));
+        }
+        let mut data = BytesMut::new();
+        while let Some(chunk) = resp.chunk().await? {
+            if data.len() + chunk.len() > max {
+                return Err(FetchError::TooLarge(max));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        Ok(data.freeze())
+    }
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .map_or(false, |sub| sub.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+// 排除私有、回环、链路本地（包括云服务的 metadata 地址）等地址
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || a == 0
+                // 100.64.0.0/10 运营商级 NAT
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                let segment = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // fc00::/7 唯一本地地址
+                    || segment & 0xfe00 == 0xfc00
+                    // fe80::/10 链路本地地址
+                    || segment & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_public_addresses_should_be_detected() {
+        let private = [
+            "127.0.0.1",
+            "10.0.0.1",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ];
+        for ip in private {
+            assert!(!is_public(ip.parse().unwrap()), "{} should be private", ip);
+        }
+        for ip in ["8.8.8.8", "151.101.1.69", "2606:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{} should be public", ip);
+        }
+    }
+
+    #[test]
+    fn allowlist_should_be_checked() {
+        let config = FetchConfig {
+            hosts: vec!["images.pexels.com".into(), "*.example.com".into()],
+            ..Default::default()
+        };
+        let allowed = |url: &str| config.allows(&Url::parse(url).unwrap()).is_ok();
+        assert!(allowed("https://images.pexels.com/a.jpg"));
+        assert!(allowed("https://cdn.example.com/a.jpg"));
+        assert!(!allowed("https://example.com/a.jpg"));
+        assert!(!allowed("https://badexample.com/a.jpg"));
+        assert!(!allowed("https://pexels.com/a.jpg"));
+        assert!(!allowed("ftp://images.pexels.com/a.jpg"));
+        assert!(!allowed("file:///etc/passwd"));
+    }
+
+    #[tokio::test]
+    async fn fetch_should_reject_private_hosts() {
+        let fetcher = Fetcher::new(FetchConfig::default());
+        // 只用 IP 地址，不会去解析域名，检查解析结果用的也是 is_public
+        for url in [
+            "http://127.0.0.1:3001/image",
+            "http://[::1]/",
+            "http://[::ffff:10.0.0.1]/",
+            "http://169.254.169.254/latest/meta-data/",
+        ] {
+            let err = fetcher.fetch(url).await.unwrap_err();
+            assert_eq!(err.status(), StatusCode::FORBIDDEN, "{}: {}", url, err);
+        }
+
+        let err = fetcher.fetch("not a url").await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -2,53 +2,95 @@ use anyhow::Result;
 use axum::{
     extract::{Extension, Path},
     handler::get,
-    http::{HeaderMap, HeaderValue, StatusCode},
-    AddExtensionLayer, Router,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    AddExtensionLayer, Json, Router,
 };
+use base64::{encode_config, URL_SAFE_NO_PAD};
 use bytes::Bytes;
-use lru::LruCache;
 use percent_encoding::{percent_decode_str, percent_encode, NON_ALPHANUMERIC};
 use serde::Deserialize;
-use std::{
-    collections::hash_map::DefaultHasher,
-    convert::TryInto,
-    hash::{Hash, Hasher},
-    sync::Arc,
-};
-use tokio::sync::Mutex;
+use sha2::{Digest, Sha256};
+use std::{convert::TryInto, sync::Arc};
 use tower::ServiceBuilder;
 use tracing::{info, instrument};
 
 // 引入 protobuf 生成的代码
 mod pb;
+// 引入 缓存
+mod cache;
+// 引入 配置
+mod config;
 // 引入 图片处理引擎
 mod engine;
+// 引入 原图抓取
+mod fetcher;
+// 引入 处理图片的线程池
+mod pool;
+// 引入 url 签名
+mod signer;
+// 引入 合并并发请求
+mod singleflight;
+// 引入 注册的水印
+mod watermarks;
 
-use engine::{Engine, Photon};
-use image::ImageOutputFormat;
+use cache::{Cache, CacheConfig};
+use config::env_or;
+use engine::{EngineKind, Meta, Watermarks};
+use fetcher::{FetchConfig, FetchError, Fetcher};
+use image::ImageFormat;
 use pb::*;
+use pool::{Pool, PoolConfig};
+use signer::Signer;
+use singleflight::Group;
+use watermarks::{Named, Registry};
 
 /// 编译、运行并访问给出的test url 进行测试
 /// cargo build --release
-/// RUST_LOG=info target/release/thumbor
+/// THUMBOR_SECRET=xxx RUST_LOG=info target/release/thumbor
+/// 对 url 签名：THUMBOR_SECRET=xxx target/release/thumbor sign <spec> <url>
+/// spec 为 meta 时生成 /meta 的 url
 #[tokio::main]
-async fn main() {
+async fn main() -> Result<()> {
     // 初始化 tracing
     tracing_subscriber::fmt::init();
-    let cache: Cache = Arc::new(Mutex::new(LruCache::new(1024)));
+    let signer = Signer::from_env()?;
+    let fetcher = Fetcher::new(FetchConfig::from_env()?);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(|s| s.as_str()) == Some("sign") {
+        match &args[1..] {
+            [spec, url] => println!("{}", signed_url(&signer, spec, url)?),
+            _ => eprintln!("Usage: thumbor sign <spec> <url>"),
+        }
+        return Ok(());
+    }
+
+    let cache = Cache::new(CacheConfig::from_env()?).await?;
+    let pool = Pool::new(&PoolConfig::from_env()?);
+    let engine: EngineKind = env_or("THUMBOR_ENGINE", EngineKind::default())?;
+    let registry = Registry::from_env()?;
+    info!("Using engine {:?}", engine);
 
     // 构建路由
     let app = Router::new()
-        .route("/image/:spec/:url", get(generate))
+        .route("/image/:sig/:spec/:url", get(generate))
+        .route("/meta/:sig/:url", get(meta))
+        .route("/filters", get(filters))
         .layer(
             ServiceBuilder::new()
                 .layer(AddExtensionLayer::new(cache))
+                .layer(AddExtensionLayer::new(signer.clone()))
+                .layer(AddExtensionLayer::new(fetcher))
+                .layer(AddExtensionLayer::new(Flights::new(Group::new())))
+                .layer(AddExtensionLayer::new(pool))
+                .layer(AddExtensionLayer::new(engine))
+                .layer(AddExtensionLayer::new(registry))
                 .into_inner(),
         );
     // 运行web服务器
     let addr = "127.0.0.1:3001".parse().unwrap();
 
-    print_test_url("https://images.pexels.com/photos/1562477/pexels-photo-1562477.jpeg?auto=compress&cs=tinysrgb&dpr=3&h=750&w=1260");
+    print_test_url(&signer, "https://images.pexels.com/photos/1562477/pexels-photo-1562477.jpeg?auto=compress&cs=tinysrgb&dpr=3&h=750&w=1260");
 
     //tracing::debug!("listening on {}", addr);
     info!("listening on {}", addr);
@@ -57,80 +99,283 @@ async fn main() {
         .serve(app.into_make_service())
         .await
         .unwrap();
+    Ok(())
 }
 
-type Cache = Arc<Mutex<LruCache<u64, Bytes>>>;
+/// 处理结果允许客户端和 CDN 缓存的时间
+const CACHE_CONTROL: &str = "public, max-age=86400";
+
+/// /meta 签名时使用的 spec，它不是合法的 ImageSpec，签名不能用在 /image 上
+const META_SPEC: &str = "meta";
+
+// 正在下载的原图，同一个 url 的并发请求共享一次下载
+type Flights = Arc<Group<Result<Source, FetchError>>>;
+
+// 原图及其内容的 hash
+#[derive(Clone)]
+struct Source {
+    hash: String,
+    data: Bytes,
+}
 
 // 参数使用serde做Deserialize, axum 自动识别并解析
 #[derive(Deserialize)]
 struct Params {
+    sig: String,
     spec: String,
     url: String,
 }
 
 // basic handler that responds with a static string
+// 每个 Extension 都是 axum 的一个 extractor，参数会比较多
+#[allow(clippy::too_many_arguments)]
 async fn generate(
-    Path(Params { spec, url }): Path<Params>,
+    Path(Params { sig, spec, url }): Path<Params>,
     Extension(cache): Extension<Cache>,
-) -> Result<(HeaderMap, Vec<u8>), StatusCode> {
+    Extension(signer): Extension<Signer>,
+    Extension(fetcher): Extension<Fetcher>,
+    Extension(flights): Extension<Flights>,
+    Extension(pool): Extension<Pool>,
+    Extension(engine): Extension<EngineKind>,
+    Extension(registry): Extension<Registry>,
+    req_headers: HeaderMap,
+) -> Result<(StatusCode, HeaderMap, Bytes), (StatusCode, String)> {
+    // 签名不对的请求不去抓取原图，也不做任何处理
+    if !signer.verify(&sig, &spec, &url) {
+        return Err((StatusCode::FORBIDDEN, "invalid signature".into()));
+    }
+
+    // 解析失败时把具体的错误返回给调用者
     let spec: ImageSpec = spec
         .as_str()
         .try_into()
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+        .map_err(|e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string()))?;
 
     let url: &str = &percent_decode_str(&url).decode_utf8_lossy();
-    let data = retrieve_image(&url, cache)
+    let source = retrieve_image(url, &cache, &fetcher, &flights)
         .await
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+        .map_err(|e| (e.status(), e.to_string()))?;
+    let (watermarks, marks) = load_watermarks(&spec, &registry, &cache, &fetcher, &flights).await?;
 
-    // 使用 image engine 处理
-    let mut engine: Photon = data
-        .try_into()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    engine.apply(&spec.specs);
+    let output = output_of(&spec, req_headers.get(header::ACCEPT), &source.data);
+    // 处理好的图片，key 由原图和水印的内容、spec、输出格式和 engine 决定
+    let spec_str = String::from(&spec);
+    let mut parts = vec![
+        source.hash.as_bytes(),
+        engine.as_str().as_bytes(),
+        spec_str.as_bytes(),
+        output.format().content_type().as_bytes(),
+    ];
+    parts.extend(marks.iter().map(|hash| hash.as_bytes()));
+    let key = digest(&parts);
+    let etag = format!("\"{}\"", key);
 
-    let image = engine.generate(ImageOutputFormat::Jpeg(85));
+    let mut headers = HeaderMap::new();
+
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(output.format().content_type()),
+    );
+    // 输出格式可能取决于 Accept，缓存需要区分
+    headers.insert(header::VARY, HeaderValue::from_static("accept"));
+    headers.insert(header::ETAG, etag.parse().unwrap());
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(CACHE_CONTROL),
+    );
+
+    if if_none_match(&req_headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, headers, Bytes::new()));
+    }
+
+    if let Some(image) = cache.get(&key).await {
+        info!("Match output cache {}", key);
+        return Ok((StatusCode::OK, headers, image));
+    }
+
+    // 使用选定的 engine 处理，放到 blocking 线程中执行
+    let process = move || engine.process(source.data, &spec.specs, &watermarks, &output);
+    // 排队的请求太多时返回 503，spec 或者原图有问题时返回 400，其他的错误返回 500
+    let image = pool
+        .run(process)
+        .await
+        .map_err(|e| (e.status(), e.to_string()))?
+        .map_err(|e| (e.status(), e.to_string()))?;
+
+    let image = Bytes::from(image);
     info!("Finished processing: image size {}", image.len());
+    cache.put(key, image.clone()).await;
 
-    let mut headers = HeaderMap::new();
+    Ok((StatusCode::OK, headers, image))
+}
+
+// 原图的宽高、格式和 EXIF，原图和 /image 共用缓存
+// 和 /image 一样需要签名，解析也放到线程池中，受同样的并发限制
+async fn meta(
+    Path((sig, url)): Path<(String, String)>,
+    Extension(cache): Extension<Cache>,
+    Extension(signer): Extension<Signer>,
+    Extension(fetcher): Extension<Fetcher>,
+    Extension(flights): Extension<Flights>,
+    Extension(pool): Extension<Pool>,
+) -> Result<Json<Meta>, (StatusCode, String)> {
+    if !signer.verify(&sig, META_SPEC, &url) {
+        return Err((StatusCode::FORBIDDEN, "invalid signature".into()));
+    }
 
-    headers.insert("content-type", HeaderValue::from_static("image/jpeg"));
-    Ok((headers, image))
+    let url: &str = &percent_decode_str(&url).decode_utf8_lossy();
+    let source = retrieve_image(url, &cache, &fetcher, &flights)
+        .await
+        .map_err(|e| (e.status(), e.to_string()))?;
+    let meta = pool
+        .run(move || Meta::read(&source.data))
+        .await
+        .map_err(|e| (e.status(), e.to_string()))?
+        .map_err(|e| (e.status(), e.to_string()))?;
+    Ok(Json(meta))
+}
+
+// 客户端缓存的版本是否和 etag 一致
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim().trim_start_matches("W/"))
+        .any(|v| v == etag || v == "*")
 }
 
-#[instrument(level = "info", skip(cache))]
-async fn retrieve_image(url: &str, cache: Cache) -> Result<Bytes> {
-    let mut hasher = DefaultHasher::new();
+// 对若干段数据做 sha256，结果用 base64 表示
+fn digest(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+        // 分隔符，避免不同的分段得到相同的结果
+        hasher.update(b"\0");
+    }
+    encode_config(hasher.finalize(), URL_SAFE_NO_PAD)
+}
 
-    url.hash(&mut hasher);
-    let key = hasher.finish();
+// 确定输出格式，没有指定时根据 Accept 选择
+// 客户端不支持 WebP 时，PNG/GIF 原图输出 PNG 以保留透明度
+fn output_of(spec: &ImageSpec, accept: Option<&HeaderValue>, data: &[u8]) -> Output {
+    let mut output = spec.output().cloned().unwrap_or_default();
+    if output.format() == output::Format::Auto {
+        let fallback = match image::guess_format(data) {
+            Ok(ImageFormat::Png) | Ok(ImageFormat::Gif) => output::Format::Png,
+            _ => output::Format::Jpeg,
+        };
+        let accept = accept.and_then(|v| v.to_str().ok()).unwrap_or_default();
+        output.set_format(output::Format::negotiate(accept, fallback));
+    }
+    output
+}
+
+// 列出支持的滤镜名称，方便前端生成选择列表
+async fn filters() -> Json<Vec<&'static str>> {
+    Json(filter::Filter::all().filter_map(|f| f.to_str()).collect())
+}
+
+// 原图按内容的 hash 保存，url 只记录对应的 hash
+#[instrument(level = "info", skip(cache, fetcher, flights))]
+async fn retrieve_image(
+    url: &str,
+    cache: &Cache,
+    fetcher: &Fetcher,
+    flights: &Flights,
+) -> Result<Source, FetchError> {
+    let url_key = digest(&[b"url", url.as_bytes()]);
+    if let Some(source) = cached_source(cache, &url_key).await {
+        return Ok(source);
+    }
+
+    flights
+        .work(&url_key, || async {
+            // 等到执行时其他请求可能已经下载好了
+            if let Some(source) = cached_source(cache, &url_key).await {
+                return Ok(source);
+            }
 
-    let g = &mut cache.lock().await;
-    let data = match g.get(&key) {
-        Some(v) => {
-            info!("Match cache {}", key);
-            // 深拷贝 from borrowed data
-            v.to_owned()
-        }
-        None => {
             info!("Retrive url");
-            let resp = reqwest::get(url).await?;
-            let data = resp.bytes().await?;
-            g.put(key, data.clone());
-            data
-        }
-    };
+            let data = fetcher.fetch(url).await?;
+            let hash = digest(&[&data]);
+            cache.put(hash.clone(), data.clone()).await;
+            cache.put(url_key.clone(), Bytes::from(hash.clone())).await;
+            Ok(Source { hash, data })
+        })
+        .await
+}
+
+// 准备 spec 中用到的水印，同时返回它们的 hash
+async fn load_watermarks(
+    spec: &ImageSpec,
+    registry: &Registry,
+    cache: &Cache,
+    fetcher: &Fetcher,
+    flights: &Flights,
+) -> Result<(Watermarks, Vec<String>), (StatusCode, String)> {
+    let mut watermarks = Watermarks::default();
+    let mut hashes = Vec::new();
+    for source in spec.watermarks().filter_map(Watermark::source) {
+        let url = match source {
+            WatermarkSource::Url(ref url) => url.clone(),
+            WatermarkSource::Name(ref name) => match registry.get(name) {
+                Some(Named::Url(url)) => url.clone(),
+                Some(Named::Data(data)) => {
+                    hashes.push(digest(&[data]));
+                    watermarks.insert(source.clone(), data.clone());
+                    continue;
+                }
+                None => {
+                    let msg = format!("unknown watermark {:?}", name);
+                    return Err((StatusCode::BAD_REQUEST, msg));
+                }
+            },
+        };
+        let mark = retrieve_image(&url, cache, fetcher, flights)
+            .await
+            .map_err(|e| (e.status(), format!("watermark: {}", e)))?;
+        hashes.push(mark.hash);
+        watermarks.insert(source, mark.data);
+    }
+    Ok((watermarks, hashes))
+}
+
+async fn cached_source(cache: &Cache, url_key: &str) -> Option<Source> {
+    let hash = cache.get(url_key).await?;
+    let hash = String::from_utf8_lossy(&hash).into_owned();
+    let data = cache.get(&hash).await?;
+    info!("Match cache {}", hash);
+    Some(Source { hash, data })
+}
+
+// 生成签名的 url，spec 可以是可读的格式或者 base64
+fn signed_url(signer: &Signer, spec: &str, url: &str) -> Result<String> {
+    let url = percent_encode(url.as_bytes(), NON_ALPHANUMERIC).to_string();
+    if spec == META_SPEC {
+        let sig = signer.sign(META_SPEC, &url);
+        return Ok(format!("http://localhost:3001/meta/{}/{}", sig, url));
+    }
 
-    Ok(data)
+    let _: ImageSpec = spec.try_into()?;
+    let sig = signer.sign(spec, &url);
+    Ok(format!(
+        "http://localhost:3001/image/{}/{}/{}",
+        sig, spec, url
+    ))
 }
 
-fn print_test_url(url: &str) {
+fn print_test_url(signer: &Signer, url: &str) {
     use std::borrow::Borrow;
     let spec1 = Spec::new_resize(500, 800, resize::SampleFilter::CatmullRom);
     let spec2 = Spec::new_watermark(20, 20);
     let spec3 = Spec::new_filter(filter::Filter::Marine);
     let image_spec = ImageSpec::new(vec![spec1, spec2, spec3]);
     let s: String = image_spec.borrow().into();
-    let test_image = percent_encode(url.as_bytes(), NON_ALPHANUMERIC).to_string();
-    println!("test url: http://localhost:3001/image/{}/{}", s, test_image);
+    println!("test url: {}", signed_url(signer, &s, url).unwrap());
+    println!(
+        "readable test url: {}",
+        signed_url(signer, &image_spec.to_string(), url).unwrap()
+    );
 }
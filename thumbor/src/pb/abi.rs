@@ -15,6 +15,10 @@ pub struct Resize {
     pub rtype: i32,
     #[prost(enumeration="resize::SampleFilter", tag="4")]
     pub filter: i32,
+    #[prost(enumeration="resize::Fit", tag="5")]
+    pub fit: i32,
+    #[prost(enumeration="Gravity", tag="6")]
+    pub gravity: i32,
 }
 /// Nested message and enum types in `Resize`.
 pub mod resize {
@@ -34,8 +38,23 @@ pub mod resize {
         Gaussian = 4,
         Lanczos3 = 5,
     }
+    /// 原图和目标的宽高比不同时的处理方式
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Fit {
+        /// 拉伸到指定的大小
+        Fill = 0,
+        /// 保持宽高比缩放到覆盖指定的大小，按 gravity 裁掉多余的部分
+        Cover = 1,
+        /// 保持宽高比缩放到指定的大小之内，空白的部分是透明的
+        Contain = 2,
+        /// 保持宽高比，宽和高都不超过指定的大小
+        Inside = 3,
+        /// 保持宽高比，宽和高都不小于指定的大小
+        Outside = 4,
+    }
 }
-/// 处理图片截取
+/// 处理图片截取，width 和 height 不为 0 时按 gravity 选择区域，忽略 (x1, y1) 和 (x2, y2)
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Crop {
     #[prost(uint32, tag="1")]
@@ -46,6 +65,12 @@ pub struct Crop {
     pub x2: u32,
     #[prost(uint32, tag="4")]
     pub y2: u32,
+    #[prost(uint32, tag="5")]
+    pub width: u32,
+    #[prost(uint32, tag="6")]
+    pub height: u32,
+    #[prost(enumeration="Gravity", tag="7")]
+    pub gravity: i32,
 }
 /// 处理水平翻转
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -73,24 +98,169 @@ pub mod filter {
     #[repr(i32)]
     pub enum Filter {
         Unspecified = 0,
+        /// https://docs.rs/photon-rs/0.3.1/photon_rs/filters/fn.filter.html
         Oceanic = 1,
         Islands = 2,
-        /// more: https://docs.rs/photon-rs/0.3.1/photon_rs/filters/fn.filter.html
         Marine = 3,
+        Seagreen = 4,
+        Flagblue = 5,
+        Liquid = 6,
+        Diamante = 7,
+        Radio = 8,
+        Twenties = 9,
+        Rosetint = 10,
+        Mauve = 11,
+        Bluechrome = 12,
+        Vintage = 13,
+        Perfume = 14,
+        Serenity = 15,
+        /// 以下滤镜在 photon_rs::filters 中有单独的函数
+        Golden = 16,
+        Lofi = 17,
+        PastelPink = 18,
+        Cali = 19,
+        Dramatic = 20,
+        Firenze = 21,
+        Obsidian = 22,
     }
 }
-/// 处理水印
+/// 处理水印，(x, y) 为水印到 anchor 所在角的距离
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Watermark {
     #[prost(uint32, tag="1")]
     pub x: u32,
     #[prost(uint32, tag="2")]
     pub y: u32,
+    /// 水印图片的 url，通过缓存抓取
+    #[prost(string, tag="3")]
+    pub url: ::prost::alloc::string::String,
+    /// 服务器配置中注册的水印名称，url 和 name 都为空时使用内置的 rust logo
+    #[prost(string, tag="4")]
+    pub name: ::prost::alloc::string::String,
+    /// 水印宽度占图片宽度的比例，0 表示使用水印原来的大小
+    #[prost(float, tag="5")]
+    pub scale: f32,
+    /// 不透明度，取值 0-1，没有设置时为 1
+    #[prost(float, optional, tag="6")]
+    pub opacity: ::core::option::Option<f32>,
+    #[prost(enumeration="watermark::Anchor", tag="7")]
+    pub anchor: i32,
+}
+/// Nested message and enum types in `Watermark`.
+pub mod watermark {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Anchor {
+        TopLeft = 0,
+        TopRight = 1,
+        BottomLeft = 2,
+        BottomRight = 3,
+        Center = 4,
+    }
+}
+/// 处理旋转，angle 为顺时针旋转的角度，90/180/270 之外的角度会裁掉超出的部分
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Rotate {
+    #[prost(float, tag="1")]
+    pub angle: f32,
+}
+/// 处理高斯模糊
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Blur {
+    #[prost(uint32, tag="1")]
+    pub radius: u32,
+}
+/// 处理锐化
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Sharpen {
+}
+/// 处理亮度，正数变亮，负数变暗
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Brightness {
+    #[prost(int32, tag="1")]
+    pub brightness: i32,
+}
+/// 处理灰度
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Grayscale {
+}
+/// 处理怀旧色调
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Sepia {
+}
+/// 处理边距，在图片四周填充颜色
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Padding {
+    #[prost(uint32, tag="1")]
+    pub top: u32,
+    #[prost(uint32, tag="2")]
+    pub right: u32,
+    #[prost(uint32, tag="3")]
+    pub bottom: u32,
+    #[prost(uint32, tag="4")]
+    pub left: u32,
+    /// 填充的颜色，格式为 0xRRGGBBAA
+    #[prost(uint32, tag="5")]
+    pub color: u32,
+}
+/// 处理文字，(x, y) 为文字左上角的位置
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Text {
+    #[prost(string, tag="1")]
+    pub text: ::prost::alloc::string::String,
+    #[prost(uint32, tag="2")]
+    pub x: u32,
+    #[prost(uint32, tag="3")]
+    pub y: u32,
+    /// 字体名称，对应字体目录下的 <font>.ttf，为空时使用内置的 Roboto 字体
+    #[prost(string, tag="4")]
+    pub font: ::prost::alloc::string::String,
+    /// 字体大小，单位为像素
+    #[prost(float, tag="5")]
+    pub size: f32,
+    /// 文字颜色，格式为 0xRRGGBBAA
+    #[prost(uint32, tag="6")]
+    pub color: u32,
+}
+/// 处理输出格式，有多个时以最后一个为准
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Output {
+    #[prost(enumeration="output::Format", tag="1")]
+    pub format: i32,
+    /// JPEG/WebP 的质量，取值 1-100，0 表示使用默认值
+    #[prost(uint32, tag="2")]
+    pub quality: u32,
+    #[prost(enumeration="output::Metadata", tag="3")]
+    pub metadata: i32,
+}
+/// Nested message and enum types in `Output`.
+pub mod output {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Format {
+        /// 根据请求的 Accept 和原图的格式选择
+        Auto = 0,
+        Jpeg = 1,
+        Png = 2,
+        Webp = 3,
+        Gif = 5,
+    }
+    /// 输出时如何处理原图的元数据
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Metadata {
+        /// 去掉所有元数据
+        Strip = 0,
+        /// 只保留 ICC 颜色配置
+        Icc = 1,
+        /// 保留 EXIF 和 ICC，图片已经按方向旋转过，EXIF 中的方向会重置
+        Keep = 2,
+    }
 }
 /// 一个 spec 可以包含上述的处理方式之一
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Spec {
-    #[prost(oneof="spec::Data", tags="1, 2, 3, 4, 5, 6, 7")]
+    #[prost(oneof="spec::Data", tags="1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16")]
     pub data: ::core::option::Option<spec::Data>,
 }
 /// Nested message and enum types in `Spec`.
@@ -111,5 +281,33 @@ pub mod spec {
         Filter(super::Filter),
         #[prost(message, tag="7")]
         Watermark(super::Watermark),
+        #[prost(message, tag="8")]
+        Rotate(super::Rotate),
+        #[prost(message, tag="9")]
+        Blur(super::Blur),
+        #[prost(message, tag="10")]
+        Sharpen(super::Sharpen),
+        #[prost(message, tag="11")]
+        Brightness(super::Brightness),
+        #[prost(message, tag="12")]
+        Grayscale(super::Grayscale),
+        #[prost(message, tag="13")]
+        Sepia(super::Sepia),
+        #[prost(message, tag="14")]
+        Padding(super::Padding),
+        #[prost(message, tag="15")]
+        Text(super::Text),
+        #[prost(message, tag="16")]
+        Output(super::Output),
     }
 }
+/// 裁剪时保留哪个区域
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Gravity {
+    Center = 0,
+    /// 信息熵最大的区域
+    Entropy = 1,
+    /// 边缘最多的区域
+    Edge = 2,
+}
@@ -1,14 +1,32 @@
 use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use image::imageops::FilterType;
 use photon_rs::transform::SamplingFilter;
 use prost::Message;
 
 mod abi; // 声明 abi.rs
+mod syntax;
 pub use abi::*;
 
 impl ImageSpec {
     pub fn new(specs: Vec<Spec>) -> Self {
         Self { specs }
     }
+
+    /// 指定的输出格式，有多个时以最后一个为准
+    pub fn output(&self) -> Option<&Output> {
+        self.specs.iter().rev().find_map(|spec| match spec.data {
+            Some(spec::Data::Output(ref v)) => Some(v),
+            _ => None,
+        })
+    }
+
+    /// 用到的所有水印
+    pub fn watermarks(&self) -> impl Iterator<Item = &Watermark> {
+        self.specs.iter().filter_map(|spec| match spec.data {
+            Some(spec::Data::Watermark(ref v)) => Some(v),
+            _ => None,
+        })
+    }
 }
 
 // 让 ImageSpec 可以生成一个字符串
@@ -23,9 +41,20 @@ impl From<&ImageSpec> for String {
 impl TryFrom<&str> for ImageSpec {
     type Error = anyhow::Error;
 
+    /// 同时支持可读的格式和 base64 编码的 protobuf
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        let data = decode_config(value, URL_SAFE_NO_PAD)?;
-        Ok(ImageSpec::decode(&data[..])?)
+        let err = match value.parse() {
+            Ok(spec) => return Ok(spec),
+            Err(e) => e,
+        };
+        // 含有 : 或 , 的一定不是 base64，直接返回可读格式的错误
+        if value.contains(&[':', ','][..]) {
+            return Err(err);
+        }
+        match decode_config(value, URL_SAFE_NO_PAD) {
+            Ok(data) => Ok(ImageSpec::decode(&data[..])?),
+            Err(_) => Err(err),
+        }
     }
 }
 
@@ -37,6 +66,98 @@ impl filter::Filter {
             filter::Filter::Oceanic => Some("oceanic"),
             filter::Filter::Islands => Some("islands"),
             filter::Filter::Marine => Some("marine"),
+            filter::Filter::Seagreen => Some("seagreen"),
+            filter::Filter::Flagblue => Some("flagblue"),
+            filter::Filter::Liquid => Some("liquid"),
+            filter::Filter::Diamante => Some("diamante"),
+            filter::Filter::Radio => Some("radio"),
+            filter::Filter::Twenties => Some("twenties"),
+            filter::Filter::Rosetint => Some("rosetint"),
+            filter::Filter::Mauve => Some("mauve"),
+            filter::Filter::Bluechrome => Some("bluechrome"),
+            filter::Filter::Vintage => Some("vintage"),
+            filter::Filter::Perfume => Some("perfume"),
+            filter::Filter::Serenity => Some("serenity"),
+            filter::Filter::Golden => Some("golden"),
+            filter::Filter::Lofi => Some("lofi"),
+            filter::Filter::PastelPink => Some("pastel_pink"),
+            filter::Filter::Cali => Some("cali"),
+            filter::Filter::Dramatic => Some("dramatic"),
+            filter::Filter::Firenze => Some("firenze"),
+            filter::Filter::Obsidian => Some("obsidian"),
+        }
+    }
+
+    /// 所有支持的滤镜
+    pub fn all() -> impl Iterator<Item = Self> {
+        (1..).map_while(Self::from_i32)
+    }
+}
+
+/// 水印图片的来源
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WatermarkSource {
+    Url(String),
+    Name(String),
+}
+
+impl Watermark {
+    /// url 优先，都为空时返回 None，使用内置的水印
+    pub fn source(&self) -> Option<WatermarkSource> {
+        match (self.url.as_str(), self.name.as_str()) {
+            ("", "") => None,
+            ("", name) => Some(WatermarkSource::Name(name.to_owned())),
+            (url, _) => Some(WatermarkSource::Url(url.to_owned())),
+        }
+    }
+
+    /// 没有设置不透明度时使用默认值 1
+    pub fn opacity_or_default(&self) -> f32 {
+        self.opacity.unwrap_or(1.0)
+    }
+}
+
+/// JPEG/WebP 默认的质量
+pub const DEFAULT_QUALITY: u8 = 85;
+
+impl Output {
+    /// 质量为 0 时使用默认值
+    pub fn quality_or_default(&self) -> u8 {
+        match self.quality {
+            0 => DEFAULT_QUALITY,
+            q => q.min(100) as u8,
+        }
+    }
+}
+
+impl output::Format {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            output::Format::Auto | output::Format::Jpeg => "image/jpeg",
+            output::Format::Png => "image/png",
+            output::Format::Webp => "image/webp",
+            output::Format::Gif => "image/gif",
+        }
+    }
+
+    /// 根据 Accept 选择输出格式，客户端明确支持时优先使用 WebP
+    pub fn negotiate(accept: &str, fallback: Self) -> Self {
+        let webp = accept
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';').map(str::trim);
+                let mime = parts.next()?;
+                // q=0 表示客户端不接受这种格式
+                match parts.any(|p| p == "q=0" || p == "q=0.0") {
+                    true => None,
+                    false => Some(mime),
+                }
+            })
+            .any(|mime| mime == output::Format::Webp.content_type());
+
+        match webp {
+            true => output::Format::Webp,
+            false => fallback,
         }
     }
 }
@@ -55,6 +176,20 @@ impl From<resize::SampleFilter> for SamplingFilter {
     }
 }
 
+// 在我们定义的 SampleFilter 和 image 的 FilterType 间转换
+impl From<resize::SampleFilter> for FilterType {
+    fn from(v: resize::SampleFilter) -> Self {
+        match v {
+            resize::SampleFilter::Undefined => FilterType::Nearest,
+            resize::SampleFilter::Nearest => FilterType::Nearest,
+            resize::SampleFilter::Triangle => FilterType::Triangle,
+            resize::SampleFilter::CatmullRom => FilterType::CatmullRom,
+            resize::SampleFilter::Gaussian => FilterType::Gaussian,
+            resize::SampleFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
 // 提供一些辅助函数，让创建一个 spec 的过程简单一些
 impl Spec {
     pub fn new_resize_seam_carve(width: u32, height: u32) -> Self {
@@ -64,6 +199,7 @@ impl Spec {
                 height,
                 rtype: resize::ResizeType::SeamCarve as i32,
                 filter: resize::SampleFilter::Undefined as i32,
+                ..Default::default()
             })),
         }
     }
@@ -75,6 +211,42 @@ impl Spec {
                 height,
                 rtype: resize::ResizeType::Normal as i32,
                 filter: filter as i32,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn new_resize_fit(width: u32, height: u32, fit: resize::Fit, gravity: Gravity) -> Self {
+        Self {
+            data: Some(spec::Data::Resize(Resize {
+                width,
+                height,
+                fit: fit as i32,
+                gravity: gravity as i32,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn new_crop(x1: u32, y1: u32, x2: u32, y2: u32) -> Self {
+        Self {
+            data: Some(spec::Data::Crop(Crop {
+                x1,
+                y1,
+                x2,
+                y2,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn new_smart_crop(width: u32, height: u32, gravity: Gravity) -> Self {
+        Self {
+            data: Some(spec::Data::Crop(Crop {
+                width,
+                height,
+                gravity: gravity as i32,
+                ..Default::default()
             })),
         }
     }
@@ -89,7 +261,101 @@ impl Spec {
 
     pub fn new_watermark(x: u32, y: u32) -> Self {
         Self {
-            data: Some(spec::Data::Watermark(Watermark { x, y })),
+            data: Some(spec::Data::Watermark(Watermark {
+                x,
+                y,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn new_output(format: output::Format, quality: u32) -> Self {
+        Self {
+            data: Some(spec::Data::Output(Output {
+                format: format as i32,
+                quality,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn new_fliph() -> Self {
+        Self {
+            data: Some(spec::Data::Fliph(Fliph {})),
+        }
+    }
+
+    pub fn new_flipv() -> Self {
+        Self {
+            data: Some(spec::Data::Flipv(Flipv {})),
+        }
+    }
+
+    pub fn new_rotate(angle: f32) -> Self {
+        Self {
+            data: Some(spec::Data::Rotate(Rotate { angle })),
+        }
+    }
+
+    pub fn new_blur(radius: u32) -> Self {
+        Self {
+            data: Some(spec::Data::Blur(Blur { radius })),
+        }
+    }
+
+    pub fn new_sharpen() -> Self {
+        Self {
+            data: Some(spec::Data::Sharpen(Sharpen {})),
+        }
+    }
+
+    pub fn new_brightness(brightness: i32) -> Self {
+        Self {
+            data: Some(spec::Data::Brightness(Brightness { brightness })),
+        }
+    }
+
+    pub fn new_grayscale() -> Self {
+        Self {
+            data: Some(spec::Data::Grayscale(Grayscale {})),
+        }
+    }
+
+    pub fn new_sepia() -> Self {
+        Self {
+            data: Some(spec::Data::Sepia(Sepia {})),
+        }
+    }
+
+    pub fn new_padding(top: u32, right: u32, bottom: u32, left: u32, color: u32) -> Self {
+        Self {
+            data: Some(spec::Data::Padding(Padding {
+                top,
+                right,
+                bottom,
+                left,
+                color,
+            })),
+        }
+    }
+
+    pub fn new_text(
+        text: impl Into<String>,
+        x: u32,
+        y: u32,
+        font: impl Into<String>,
+        size: f32,
+        color: u32,
+    ) -> Self {
+        Self {
+            data: Some(spec::Data::Text(Text {
+                text: text.into(),
+                x,
+                y,
+                font: font.into(),
+                size,
+                color,
+            })),
         }
     }
 }
@@ -107,4 +373,53 @@ mod tests {
         let s: String = image_spec.borrow().into();
         assert_eq!(image_spec, s.as_str().try_into().unwrap());
     }
+
+    #[test]
+    fn all_filters_should_have_name() {
+        let names: Vec<_> = filter::Filter::all().map(|f| f.to_str()).collect();
+        assert_eq!(names.len(), 22);
+        assert!(names.iter().all(Option::is_some));
+        assert_eq!(names[2], Some("marine"));
+    }
+
+    #[test]
+    fn output_format_should_be_negotiated() {
+        use output::Format;
+
+        let accept = "image/avif,image/webp,image/apng,image/*,*/*;q=0.8";
+        assert_eq!(Format::negotiate(accept, Format::Jpeg), Format::Webp);
+        let accept = "image/avif,image/webp;q=0,*/*";
+        assert_eq!(Format::negotiate(accept, Format::Png), Format::Png);
+        assert_eq!(Format::negotiate("*/*", Format::Png), Format::Png);
+        assert_eq!(Format::negotiate("", Format::Jpeg), Format::Jpeg);
+    }
+
+    #[test]
+    fn last_output_spec_should_win() {
+        let image_spec = ImageSpec::new(vec![
+            Spec::new_output(output::Format::Png, 0),
+            Spec::new_grayscale(),
+            Spec::new_output(output::Format::Webp, 70),
+        ]);
+        let output = image_spec.output().unwrap();
+        assert_eq!(output.format(), output::Format::Webp);
+        assert_eq!(output.quality_or_default(), 70);
+        assert_eq!(Output::default().quality_or_default(), DEFAULT_QUALITY);
+    }
+
+    #[test]
+    fn new_specs_could_be_decoded() {
+        let image_spec = ImageSpec::new(vec![
+            Spec::new_rotate(90.0),
+            Spec::new_blur(3),
+            Spec::new_sharpen(),
+            Spec::new_brightness(-20),
+            Spec::new_grayscale(),
+            Spec::new_sepia(),
+            Spec::new_padding(10, 20, 10, 20, 0xffffffff),
+            Spec::new_text("hello", 10, 10, "roboto", 24.0, 0x000000ff),
+        ]);
+        let s: String = image_spec.borrow().into();
+        assert_eq!(image_spec, s.as_str().try_into().unwrap());
+    }
 }
@@ -0,0 +1,644 @@
+use super::*;
+use anyhow::{anyhow, bail, Result};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use std::{fmt, iter::Peekable, str::FromStr, vec};
+
+// 文字中需要转义的字符
+const TEXT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'%')
+    .add(b',')
+    .add(b'/')
+    .add(b':')
+    .add(b'?')
+    .add(b'#');
+
+/// 可读的格式：resize:500x800,filter:marine,watermark:20x20
+impl fmt::Display for ImageSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, spec) in self.specs.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", spec)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ImageSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let specs = s
+            .split(',')
+            .enumerate()
+            .map(|(i, item)| {
+                item.parse()
+                    .map_err(|e| anyhow!("invalid spec #{} {:?}: {}", i + 1, item, e))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self::new(specs))
+    }
+}
+
+impl fmt::Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.data {
+            Some(spec::Data::Resize(ref v)) => {
+                write!(f, "resize:{}x{}", v.width, v.height)?;
+                match (v.rtype(), sample_filter_name(v.filter())) {
+                    (resize::ResizeType::SeamCarve, _) => f.write_str(":seam_carve")?,
+                    (_, Some(name)) => write!(f, ":{}", name)?,
+                    (_, None) => {}
+                }
+                if v.fit() != resize::Fit::Fill {
+                    write!(f, ":fit={}", fit_name(v.fit()))?;
+                }
+                if v.gravity() != Gravity::Center {
+                    write!(f, ":gravity={}", gravity_name(v.gravity()))?;
+                }
+                Ok(())
+            }
+            Some(spec::Data::Crop(ref v)) if v.width > 0 || v.height > 0 => {
+                write!(f, "crop:{}x{}", v.width, v.height)?;
+                write!(f, ":gravity={}", gravity_name(v.gravity()))
+            }
+            Some(spec::Data::Crop(ref v)) => {
+                write!(f, "crop:{}x{}:{}x{}", v.x1, v.y1, v.x2, v.y2)
+            }
+            Some(spec::Data::Flipv(_)) => f.write_str("flipv"),
+            Some(spec::Data::Fliph(_)) => f.write_str("fliph"),
+            Some(spec::Data::Contrast(ref v)) => write!(f, "contrast:{}", v.contrast),
+            Some(spec::Data::Filter(ref v)) => {
+                write!(f, "filter:{}", v.filter().to_str().unwrap_or("none"))
+            }
+            Some(spec::Data::Watermark(ref v)) => {
+                write!(f, "watermark:{}x{}", v.x, v.y)?;
+                if v.anchor() != watermark::Anchor::TopLeft {
+                    write!(f, ":anchor={}", anchor_name(v.anchor()))?;
+                }
+                if v.scale != 0.0 {
+                    write!(f, ":scale={}", v.scale)?;
+                }
+                if let Some(opacity) = v.opacity {
+                    write!(f, ":opacity={}", opacity)?;
+                }
+                if !v.name.is_empty() {
+                    write!(f, ":name={}", utf8_percent_encode(&v.name, TEXT))?;
+                }
+                if !v.url.is_empty() {
+                    write!(f, ":url={}", utf8_percent_encode(&v.url, TEXT))?;
+                }
+                Ok(())
+            }
+            Some(spec::Data::Rotate(ref v)) => write!(f, "rotate:{}", v.angle),
+            Some(spec::Data::Blur(ref v)) => write!(f, "blur:{}", v.radius),
+            Some(spec::Data::Sharpen(_)) => f.write_str("sharpen"),
+            Some(spec::Data::Brightness(ref v)) => write!(f, "brightness:{}", v.brightness),
+            Some(spec::Data::Grayscale(_)) => f.write_str("grayscale"),
+            Some(spec::Data::Sepia(_)) => f.write_str("sepia"),
+            Some(spec::Data::Padding(ref v)) => {
+                write!(f, "padding:{}:{}:{}:{}", v.top, v.right, v.bottom, v.left)?;
+                match v.color {
+                    0 => Ok(()),
+                    color => write!(f, ":{:08x}", color),
+                }
+            }
+            Some(spec::Data::Text(ref v)) => write!(
+                f,
+                "text:{}x{}:{}:{:08x}:{}:{}",
+                v.x,
+                v.y,
+                v.size,
+                v.color,
+                v.font,
+                utf8_percent_encode(&v.text, TEXT)
+            ),
+            Some(spec::Data::Output(ref v)) => {
+                write!(f, "output:{}", format_name(v.format()))?;
+                if v.quality != 0 {
+                    write!(f, ":{}", v.quality)?;
+                }
+                match v.metadata() {
+                    output::Metadata::Strip => Ok(()),
+                    metadata => write!(f, ":metadata={}", metadata_name(metadata)),
+                }
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Spec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, args) = s.split_once(':').unwrap_or((s, ""));
+        let mut args = Args::new(args);
+
+        let spec = match name {
+            "resize" => {
+                let (width, height) = args.pair("size", "<width>x<height>")?;
+                let mut resize = Resize {
+                    width,
+                    height,
+                    ..Default::default()
+                };
+                match args.positional() {
+                    None => {}
+                    Some("seam_carve") => resize.set_rtype(resize::ResizeType::SeamCarve),
+                    Some(v) => resize.set_filter(parse_sample_filter(v)?),
+                }
+                while let Some((key, value)) = args.option()? {
+                    match key {
+                        "fit" => resize.set_fit(parse_fit(value)?),
+                        "gravity" => resize.set_gravity(parse_gravity(value)?),
+                        _ => bail!("unknown option {:?}", key),
+                    }
+                }
+                Spec {
+                    data: Some(spec::Data::Resize(resize)),
+                }
+            }
+            "crop" => {
+                let (x1, y1) = args.pair("top left corner", "<x1>x<y1>")?;
+                // crop:<width>x<height>:gravity=<gravity> 按 gravity 选择区域
+                if args.at_option() {
+                    let mut gravity = Gravity::Center;
+                    while let Some((key, value)) = args.option()? {
+                        match key {
+                            "gravity" => gravity = parse_gravity(value)?,
+                            _ => bail!("unknown option {:?}", key),
+                        }
+                    }
+                    Spec::new_smart_crop(x1, y1, gravity)
+                } else {
+                    let (x2, y2) = args.pair("bottom right corner", "<x2>x<y2>")?;
+                    Spec::new_crop(x1, y1, x2, y2)
+                }
+            }
+            "flipv" => Spec::new_flipv(),
+            "fliph" => Spec::new_fliph(),
+            "contrast" => Spec {
+                data: Some(spec::Data::Contrast(Contrast {
+                    contrast: args.parse("contrast")?,
+                })),
+            },
+            "filter" => Spec::new_filter(parse_filter(args.next("filter name")?)?),
+            "watermark" => {
+                let (x, y) = args.pair("position", "<x>x<y>")?;
+                let mut watermark = Watermark {
+                    x,
+                    y,
+                    ..Default::default()
+                };
+                while let Some((key, value)) = args.option()? {
+                    let invalid = || anyhow!("invalid {} {:?}", key, value);
+                    match key {
+                        "anchor" => watermark.set_anchor(parse_anchor(value)?),
+                        "scale" => watermark.scale = value.parse().map_err(|_| invalid())?,
+                        "opacity" => {
+                            watermark.opacity = Some(value.parse().map_err(|_| invalid())?)
+                        }
+                        "name" => watermark.name = decode(key, value)?,
+                        "url" => watermark.url = decode(key, value)?,
+                        _ => bail!("unknown option {:?}", key),
+                    }
+                }
+                Spec {
+                    data: Some(spec::Data::Watermark(watermark)),
+                }
+            }
+            "rotate" => Spec::new_rotate(args.parse("angle")?),
+            "blur" => Spec::new_blur(args.parse("radius")?),
+            "sharpen" => Spec::new_sharpen(),
+            "brightness" => Spec::new_brightness(args.parse("brightness")?),
+            "grayscale" => Spec::new_grayscale(),
+            "sepia" => Spec::new_sepia(),
+            "padding" => {
+                let top = args.parse("top")?;
+                let right = args.parse("right")?;
+                let bottom = args.parse("bottom")?;
+                let left = args.parse("left")?;
+                let color = match args.optional() {
+                    Some(v) => parse_color(v)?,
+                    None => 0,
+                };
+                Spec::new_padding(top, right, bottom, left, color)
+            }
+            "text" => {
+                let (x, y) = args.pair("position", "<x>x<y>")?;
+                let size = args.parse("font size")?;
+                let color = parse_color(args.next("color")?)?;
+                let font = args.next("font")?;
+                let text = decode("text", args.next("text")?)?;
+                Spec::new_text(text, x, y, font, size, color)
+            }
+            "output" => {
+                let format = parse_format(args.next("format")?)?;
+                let quality = match args.positional() {
+                    Some(v) => v.parse().map_err(|_| anyhow!("invalid quality {:?}", v))?,
+                    None => 0,
+                };
+                let mut output = Output {
+                    format: format as _,
+                    quality,
+                    ..Default::default()
+                };
+                while let Some((key, value)) = args.option()? {
+                    match key {
+                        "metadata" => output.set_metadata(parse_metadata(value)?),
+                        _ => bail!("unknown option {:?}", key),
+                    }
+                }
+                Spec {
+                    data: Some(spec::Data::Output(output)),
+                }
+            }
+            "" => bail!("empty operation"),
+            _ => bail!("unknown operation {:?}", name),
+        };
+
+        args.finish()?;
+        Ok(spec)
+    }
+}
+
+// 按 : 分隔的参数，位置参数之后是可选的 <name>=<value>
+struct Args<'a>(Peekable<vec::IntoIter<&'a str>>);
+
+impl<'a> Args<'a> {
+    fn new(args: &'a str) -> Self {
+        let items = match args {
+            "" => vec![],
+            args => args.split(':').collect(),
+        };
+        Self(items.into_iter().peekable())
+    }
+
+    fn optional(&mut self) -> Option<&'a str> {
+        self.0.next()
+    }
+
+    // 下一个参数是否是 <name>=<value>
+    fn at_option(&mut self) -> bool {
+        self.0.peek().map_or(false, |v| v.contains('='))
+    }
+
+    // 可选的位置参数，后面是 <name>=<value> 时返回 None
+    fn positional(&mut self) -> Option<&'a str> {
+        match self.at_option() {
+            true => None,
+            false => self.optional(),
+        }
+    }
+
+    fn option(&mut self) -> Result<Option<(&'a str, &'a str)>> {
+        match self.optional() {
+            Some(v) => match v.split_once('=') {
+                Some(option) => Ok(Some(option)),
+                None => bail!("invalid option {:?}, expected <name>=<value>", v),
+            },
+            None => Ok(None),
+        }
+    }
+
+    fn next(&mut self, what: &str) -> Result<&'a str> {
+        self.optional().ok_or_else(|| anyhow!("missing {}", what))
+    }
+
+    fn parse<T: FromStr>(&mut self, what: &str) -> Result<T> {
+        let v = self.next(what)?;
+        v.parse().map_err(|_| anyhow!("invalid {} {:?}", what, v))
+    }
+
+    fn pair(&mut self, what: &str, expected: &str) -> Result<(u32, u32)> {
+        let v = self.next(what)?;
+        let err = || anyhow!("invalid {} {:?}, expected {}", what, v, expected);
+        let (a, b) = v.split_once('x').ok_or_else(err)?;
+        Ok((a.parse().map_err(|_| err())?, b.parse().map_err(|_| err())?))
+    }
+
+    fn finish(mut self) -> Result<()> {
+        match self.optional() {
+            Some(v) => bail!("unexpected argument {:?}", v),
+            None => Ok(()),
+        }
+    }
+}
+
+// 解码 percent-encoding 转义过的文字
+fn decode(what: &str, v: &str) -> Result<String> {
+    let s = percent_decode_str(v)
+        .decode_utf8()
+        .map_err(|_| anyhow!("{} {:?} is not valid utf-8", what, v))?;
+    Ok(s.into_owned())
+}
+
+// 颜色的格式为 RRGGBB 或 RRGGBBAA
+fn parse_color(v: &str) -> Result<u32> {
+    let err = || anyhow!("invalid color {:?}, expected RRGGBB or RRGGBBAA", v);
+    let color = u32::from_str_radix(v, 16).map_err(|_| err())?;
+    match v.len() {
+        6 => Ok(color << 8 | 0xff),
+        8 => Ok(color),
+        _ => Err(err()),
+    }
+}
+
+fn parse_filter(v: &str) -> Result<filter::Filter> {
+    if v == "none" {
+        return Ok(filter::Filter::Unspecified);
+    }
+    filter::Filter::all()
+        .find(|f| f.to_str() == Some(v))
+        .ok_or_else(|| anyhow!("unknown filter {:?}", v))
+}
+
+const SAMPLE_FILTERS: &[(resize::SampleFilter, &str)] = &[
+    (resize::SampleFilter::Nearest, "nearest"),
+    (resize::SampleFilter::Triangle, "triangle"),
+    (resize::SampleFilter::CatmullRom, "catmull_rom"),
+    (resize::SampleFilter::Gaussian, "gaussian"),
+    (resize::SampleFilter::Lanczos3, "lanczos3"),
+];
+
+fn sample_filter_name(filter: resize::SampleFilter) -> Option<&'static str> {
+    SAMPLE_FILTERS
+        .iter()
+        .find(|(f, _)| *f == filter)
+        .map(|(_, name)| *name)
+}
+
+fn parse_sample_filter(v: &str) -> Result<resize::SampleFilter> {
+    SAMPLE_FILTERS
+        .iter()
+        .find(|(_, name)| *name == v)
+        .map(|(f, _)| *f)
+        .ok_or_else(|| anyhow!("unknown resize filter {:?}", v))
+}
+
+const ANCHORS: &[(watermark::Anchor, &str)] = &[
+    (watermark::Anchor::TopLeft, "top_left"),
+    (watermark::Anchor::TopRight, "top_right"),
+    (watermark::Anchor::BottomLeft, "bottom_left"),
+    (watermark::Anchor::BottomRight, "bottom_right"),
+    (watermark::Anchor::Center, "center"),
+];
+
+fn anchor_name(anchor: watermark::Anchor) -> &'static str {
+    ANCHORS
+        .iter()
+        .find(|(a, _)| *a == anchor)
+        .map(|(_, name)| *name)
+        .unwrap()
+}
+
+fn parse_anchor(v: &str) -> Result<watermark::Anchor> {
+    ANCHORS
+        .iter()
+        .find(|(_, name)| *name == v)
+        .map(|(a, _)| *a)
+        .ok_or_else(|| anyhow!("unknown anchor {:?}", v))
+}
+
+const FITS: &[(resize::Fit, &str)] = &[
+    (resize::Fit::Fill, "fill"),
+    (resize::Fit::Cover, "cover"),
+    (resize::Fit::Contain, "contain"),
+    (resize::Fit::Inside, "inside"),
+    (resize::Fit::Outside, "outside"),
+];
+
+fn fit_name(fit: resize::Fit) -> &'static str {
+    FITS.iter()
+        .find(|(f, _)| *f == fit)
+        .map(|(_, name)| *name)
+        .unwrap()
+}
+
+fn parse_fit(v: &str) -> Result<resize::Fit> {
+    FITS.iter()
+        .find(|(_, name)| *name == v)
+        .map(|(f, _)| *f)
+        .ok_or_else(|| anyhow!("unknown fit {:?}", v))
+}
+
+const GRAVITIES: &[(Gravity, &str)] = &[
+    (Gravity::Center, "center"),
+    (Gravity::Entropy, "entropy"),
+    (Gravity::Edge, "edge"),
+];
+
+fn gravity_name(gravity: Gravity) -> &'static str {
+    GRAVITIES
+        .iter()
+        .find(|(g, _)| *g == gravity)
+        .map(|(_, name)| *name)
+        .unwrap()
+}
+
+fn parse_gravity(v: &str) -> Result<Gravity> {
+    GRAVITIES
+        .iter()
+        .find(|(_, name)| *name == v)
+        .map(|(g, _)| *g)
+        .ok_or_else(|| anyhow!("unknown gravity {:?}", v))
+}
+
+const FORMATS: &[(output::Format, &str)] = &[
+    (output::Format::Auto, "auto"),
+    (output::Format::Jpeg, "jpeg"),
+    (output::Format::Png, "png"),
+    (output::Format::Webp, "webp"),
+    (output::Format::Gif, "gif"),
+];
+
+fn format_name(format: output::Format) -> &'static str {
+    FORMATS
+        .iter()
+        .find(|(f, _)| *f == format)
+        .map(|(_, name)| *name)
+        .unwrap()
+}
+
+fn parse_format(v: &str) -> Result<output::Format> {
+    FORMATS
+        .iter()
+        .find(|(_, name)| *name == v)
+        .map(|(f, _)| *f)
+        .ok_or_else(|| anyhow!("unknown output format {:?}", v))
+}
+
+const METADATA: &[(output::Metadata, &str)] = &[
+    (output::Metadata::Strip, "strip"),
+    (output::Metadata::Icc, "icc"),
+    (output::Metadata::Keep, "keep"),
+];
+
+fn metadata_name(metadata: output::Metadata) -> &'static str {
+    METADATA
+        .iter()
+        .find(|(m, _)| *m == metadata)
+        .map(|(_, name)| *name)
+        .unwrap()
+}
+
+fn parse_metadata(v: &str) -> Result<output::Metadata> {
+    METADATA
+        .iter()
+        .find(|(_, name)| *name == v)
+        .map(|(m, _)| *m)
+        .ok_or_else(|| anyhow!("unknown metadata mode {:?}", v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Borrow;
+
+    #[test]
+    fn readable_spec_should_be_parsed() {
+        let spec: ImageSpec = "resize:500x800,filter:marine,watermark:20x20"
+            .parse()
+            .unwrap();
+        let expected = ImageSpec::new(vec![
+            Spec::new_resize(500, 800, resize::SampleFilter::Undefined),
+            Spec::new_filter(filter::Filter::Marine),
+            Spec::new_watermark(20, 20),
+        ]);
+        assert_eq!(spec, expected);
+    }
+
+    #[test]
+    fn readable_spec_should_round_trip() {
+        let image_spec = ImageSpec::new(vec![
+            Spec::new_resize(500, 800, resize::SampleFilter::CatmullRom),
+            Spec::new_resize_seam_carve(300, 300),
+            Spec::new_resize_fit(300, 200, resize::Fit::Cover, Gravity::Entropy),
+            Spec::new_resize_fit(300, 200, resize::Fit::Inside, Gravity::Center),
+            Spec::new_crop(10, 20, 200, 300),
+            Spec::new_smart_crop(100, 100, Gravity::Edge),
+            Spec::new_filter(filter::Filter::PastelPink),
+            Spec {
+                data: Some(spec::Data::Watermark(Watermark {
+                    x: 10,
+                    y: 10,
+                    url: "https://example.com/logo.png?v=1".into(),
+                    scale: 0.2,
+                    opacity: Some(0.5),
+                    anchor: watermark::Anchor::BottomRight as _,
+                    ..Default::default()
+                })),
+            },
+            Spec {
+                data: Some(spec::Data::Watermark(Watermark {
+                    name: "brand".into(),
+                    opacity: Some(0.0),
+                    anchor: watermark::Anchor::Center as _,
+                    ..Default::default()
+                })),
+            },
+            Spec::new_rotate(45.5),
+            Spec::new_brightness(-20),
+            Spec::new_padding(10, 20, 10, 20, 0xff0000ff),
+            Spec::new_padding(5, 5, 5, 5, 0),
+            Spec::new_text("hello, world: 100%", 10, 10, "roboto", 24.0, 0x000000ff),
+            Spec::new_output(output::Format::Webp, 80),
+            Spec::new_output(output::Format::Auto, 0),
+            Spec {
+                data: Some(spec::Data::Output(Output {
+                    format: output::Format::Jpeg as _,
+                    quality: 90,
+                    metadata: output::Metadata::Keep as _,
+                })),
+            },
+            Spec {
+                data: Some(spec::Data::Output(Output {
+                    format: output::Format::Png as _,
+                    metadata: output::Metadata::Icc as _,
+                    ..Default::default()
+                })),
+            },
+        ]);
+        let s = image_spec.to_string();
+        assert_eq!(
+            s,
+            "resize:500x800:catmull_rom,resize:300x300:seam_carve,\
+             resize:300x200:fit=cover:gravity=entropy,resize:300x200:fit=inside,\
+             crop:10x20:200x300,crop:100x100:gravity=edge,\
+             filter:pastel_pink,\
+             watermark:10x10:anchor=bottom_right:scale=0.2:opacity=0.5:\
+             url=https%3A%2F%2Fexample.com%2Flogo.png%3Fv=1,\
+             watermark:0x0:anchor=center:opacity=0:name=brand,rotate:45.5,brightness:-20,padding:10:20:10:20:ff0000ff,\
+             padding:5:5:5:5,text:10x10:24:000000ff:roboto:hello%2C%20world%3A%20100%25,\
+             output:webp:80,output:auto,output:jpeg:90:metadata=keep,output:png:metadata=icc"
+        );
+        assert_eq!(s.parse::<ImageSpec>().unwrap(), image_spec);
+    }
+
+    #[test]
+    fn invalid_spec_should_report_precise_error() {
+        let cases = [
+            (
+                "resize:500x",
+                r#"invalid spec #1 "resize:500x": invalid size "500x", expected <width>x<height>"#,
+            ),
+            (
+                "resize:500x800,filter:foo",
+                r#"invalid spec #2 "filter:foo": unknown filter "foo""#,
+            ),
+            ("blur", r#"invalid spec #1 "blur": missing radius"#),
+            (
+                "sepia:1",
+                r#"invalid spec #1 "sepia:1": unexpected argument "1""#,
+            ),
+            ("grayscale,,sepia", r#"invalid spec #2 "": empty operation"#),
+            (
+                "padding:1:2:3:4:red",
+                r#"invalid spec #1 "padding:1:2:3:4:red": invalid color "red", expected RRGGBB or RRGGBBAA"#,
+            ),
+            (
+                "watermark:0x0:anchor=middle",
+                r#"invalid spec #1 "watermark:0x0:anchor=middle": unknown anchor "middle""#,
+            ),
+            (
+                "watermark:0x0:size=2",
+                r#"invalid spec #1 "watermark:0x0:size=2": unknown option "size""#,
+            ),
+            (
+                "resize:300x200:lanczos3:fit=stretch",
+                r#"invalid spec #1 "resize:300x200:lanczos3:fit=stretch": unknown fit "stretch""#,
+            ),
+            (
+                "crop:100x100:200",
+                r#"invalid spec #1 "crop:100x100:200": invalid bottom right corner "200", expected <x2>x<y2>"#,
+            ),
+            (
+                "output:jpeg:metadata=all",
+                r#"invalid spec #1 "output:jpeg:metadata=all": unknown metadata mode "all""#,
+            ),
+            (
+                "zoom:2",
+                r#"invalid spec #1 "zoom:2": unknown operation "zoom""#,
+            ),
+        ];
+        for (s, msg) in cases {
+            let err = s.parse::<ImageSpec>().unwrap_err();
+            assert_eq!(err.to_string(), msg);
+        }
+    }
+
+    #[test]
+    fn both_syntaxes_should_be_accepted() {
+        let image_spec = ImageSpec::new(vec![
+            Spec::new_resize(600, 600, resize::SampleFilter::CatmullRom),
+            Spec::new_grayscale(),
+        ]);
+        let encoded: String = image_spec.borrow().into();
+        let readable = image_spec.to_string();
+        assert_eq!(image_spec, encoded.as_str().try_into().unwrap());
+        assert_eq!(image_spec, readable.as_str().try_into().unwrap());
+    }
+}
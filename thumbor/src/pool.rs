@@ -0,0 +1,180 @@
+use crate::config::env_or;
+use anyhow::Result;
+use axum::http::StatusCode;
+use std::{sync::Arc, thread};
+use thiserror::Error;
+use tokio::sync::Semaphore;
+
+/// 处理图片的并发配置
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    /// 同时处理图片的数量，默认为 CPU 核数
+    pub workers: usize,
+    /// 除了正在处理的，最多还有多少个请求在排队，超出时直接返回 503
+    pub queue_depth: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            workers: thread::available_parallelism().map_or(4, |n| n.get()),
+            queue_depth: 64,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// 从环境变量读取配置，没有设置的使用默认值
+    pub fn from_env() -> Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            workers: env_or("THUMBOR_WORKERS", default.workers)?.max(1),
+            queue_depth: env_or("THUMBOR_QUEUE_DEPTH", default.queue_depth)?,
+        })
+    }
+
+    /// 允许同时处理和排队的任务数量
+    pub fn max_in_flight(&self) -> usize {
+        self.workers + self.queue_depth
+    }
+}
+
+/// 线程池的错误，排队的请求太多时返回 503
+#[derive(Error, Debug)]
+pub enum PoolError {
+    #[error("too many requests, try again later")]
+    Overloaded,
+    #[error("Failed to process image: {0}")]
+    Internal(String),
+}
+
+impl PoolError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// 在 blocking 线程中处理图片，不阻塞 tokio 的 worker，同时最多执行 workers 个，
+/// 正在处理和排队的任务超过 max_in_flight 时直接拒绝
+#[derive(Debug, Clone)]
+pub struct Pool {
+    permits: Arc<Semaphore>,
+    slots: Arc<Semaphore>,
+}
+
+impl Pool {
+    pub fn new(config: &PoolConfig) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(config.workers)),
+            slots: Arc::new(Semaphore::new(config.max_in_flight())),
+        }
+    }
+
+    pub async fn run<F, T>(&self, f: F) -> Result<T, PoolError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let slot = self
+            .slots
+            .clone()
+            .try_acquire_owned()
+            .map_err(|_| PoolError::Overloaded)?;
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| PoolError::Internal(e.to_string()))?;
+        // permit 跟着任务走，请求被取消了也要等处理结束才释放
+        tokio::task::spawn_blocking(move || {
+            let _permits = (slot, permit);
+            f()
+        })
+        .await
+        .map_err(|e| PoolError::Internal(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    #[tokio::test]
+    async fn pool_should_limit_concurrency() {
+        let pool = Pool::new(&PoolConfig {
+            workers: 2,
+            queue_depth: 6,
+        });
+        let running = Arc::new(AtomicUsize::new(0));
+        let max = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..8)
+            .map(|i| {
+                let pool = pool.clone();
+                let running = running.clone();
+                let max = max.clone();
+                tokio::spawn(async move {
+                    pool.run(move || {
+                        let n = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        max.fetch_max(n, Ordering::SeqCst);
+                        thread::sleep(Duration::from_millis(20));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                        i
+                    })
+                    .await
+                })
+            })
+            .collect();
+        for (i, task) in tasks.into_iter().enumerate() {
+            assert_eq!(task.await.unwrap().unwrap(), i);
+        }
+        assert_eq!(max.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn pool_should_reject_when_queue_is_full() {
+        let pool = Pool::new(&PoolConfig {
+            workers: 1,
+            queue_depth: 1,
+        });
+        let (tx, rx) = std::sync::mpsc::channel::<()>();
+        let rx = Arc::new(std::sync::Mutex::new(rx));
+        let blocked = |pool: &Pool| {
+            let pool = pool.clone();
+            let rx = rx.clone();
+            tokio::spawn(async move { pool.run(move || rx.lock().unwrap().recv().unwrap()).await })
+        };
+
+        // 一个正在处理，一个在排队
+        let tasks = [blocked(&pool), blocked(&pool)];
+        while pool.slots.available_permits() > 0 {
+            tokio::task::yield_now().await;
+        }
+        let err = pool.run(|| 42).await.unwrap_err();
+        assert!(matches!(err, PoolError::Overloaded));
+        assert_eq!(err.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        for task in tasks {
+            tx.send(()).unwrap();
+            task.await.unwrap().unwrap();
+        }
+        assert_eq!(pool.run(|| 42).await.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn panic_in_pool_should_be_error() {
+        let pool = Pool::new(&PoolConfig::default());
+        let result = pool.run(|| panic!("oops")).await;
+        assert!(result.is_err());
+        // panic 之后 permit 也会释放
+        assert_eq!(pool.run(|| 42).await.unwrap(), 42);
+    }
+}
@@ -0,0 +1,82 @@
+use anyhow::{anyhow, Result};
+use base64::{decode_config, encode_config, URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+/// 读取签名密钥的环境变量
+pub const SECRET_ENV: &str = "THUMBOR_SECRET";
+
+/// 用 HMAC-SHA256 对 url 中的 spec 和原图 url 签名，防止被随意调用
+#[derive(Clone)]
+pub struct Signer {
+    mac: Hmac<Sha256>,
+}
+
+impl Signer {
+    pub fn new(secret: impl AsRef<[u8]>) -> Result<Self> {
+        let secret = secret.as_ref();
+        if secret.is_empty() {
+            return Err(anyhow!("secret should not be empty"));
+        }
+        let mac = Hmac::new_from_slice(secret).map_err(|e| anyhow!("invalid secret: {}", e))?;
+        Ok(Self { mac })
+    }
+
+    /// 从环境变量 THUMBOR_SECRET 中读取密钥
+    pub fn from_env() -> Result<Self> {
+        let secret = std::env::var(SECRET_ENV)
+            .map_err(|_| anyhow!("environment variable {} should be set", SECRET_ENV))?;
+        Self::new(secret)
+    }
+
+    /// 对 url 中的 spec 和 url 两段（编码后的原始字符串）签名
+    pub fn sign(&self, spec: &str, url: &str) -> String {
+        let mac = self.mac_of(spec, url);
+        encode_config(mac.finalize().into_bytes(), URL_SAFE_NO_PAD)
+    }
+
+    /// 校验签名，比较时间恒定
+    pub fn verify(&self, sig: &str, spec: &str, url: &str) -> bool {
+        match decode_config(sig, URL_SAFE_NO_PAD) {
+            Ok(sig) => self.mac_of(spec, url).verify(&sig).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn mac_of(&self, spec: &str, url: &str) -> Hmac<Sha256> {
+        let mut mac = self.mac.clone();
+        mac.update(spec.as_bytes());
+        mac.update(b"/");
+        mac.update(url.as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_url_should_be_verified() {
+        let signer = Signer::new("secret").unwrap();
+        let sig = signer.sign("resize:500x800", "https%3A%2F%2Fexample%2Ecom");
+        assert!(signer.verify(&sig, "resize:500x800", "https%3A%2F%2Fexample%2Ecom"));
+    }
+
+    #[test]
+    fn tampered_url_should_be_rejected() {
+        let signer = Signer::new("secret").unwrap();
+        let sig = signer.sign("resize:500x800", "https%3A%2F%2Fexample%2Ecom");
+        assert!(!signer.verify(&sig, "resize:5000x8000", "https%3A%2F%2Fexample%2Ecom"));
+        assert!(!signer.verify(&sig, "resize:500x800", "https%3A%2F%2Fevil%2Ecom"));
+        assert!(!signer.verify("not-a-signature", "resize:500x800", "x"));
+
+        let other = Signer::new("another secret").unwrap();
+        assert!(!other.verify(&sig, "resize:500x800", "https%3A%2F%2Fexample%2Ecom"));
+    }
+
+    #[test]
+    fn empty_secret_should_be_rejected() {
+        assert!(Signer::new("").is_err());
+    }
+}
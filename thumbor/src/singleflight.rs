@@ -0,0 +1,110 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::OnceCell;
+
+/// 合并相同 key 的并发请求：同一时间只有一个在执行，其他的等待并共享它的结果
+pub struct Group<T> {
+    calls: Mutex<HashMap<String, Arc<OnceCell<T>>>>,
+}
+
+impl<T> Default for Group<T> {
+    fn default() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> Group<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 执行 f，如果相同 key 的调用正在执行，等待它的结果
+    /// 执行中的调用被取消时，由等待者中的一个接着执行自己的 f
+    pub async fn work<F, Fut>(&self, key: &str, f: F) -> T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let call = self
+            .calls
+            .lock()
+            .unwrap()
+            .entry(key.to_owned())
+            .or_default()
+            .clone();
+        let result = call.get_or_init(f).await.clone();
+
+        // 执行完就移除，之后的调用重新执行，不会一直拿到旧的结果
+        let mut calls = self.calls.lock().unwrap();
+        if calls.get(key).map_or(false, |c| Arc::ptr_eq(c, &call)) {
+            calls.remove(key);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    #[tokio::test]
+    async fn concurrent_calls_should_be_merged() {
+        let group = Arc::new(Group::new());
+        let count = Arc::new(AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..10)
+            .map(|_| {
+                let group = group.clone();
+                let count = count.clone();
+                tokio::spawn(async move {
+                    group
+                        .work("k1", || async {
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            count.fetch_add(1, Ordering::SeqCst)
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await.unwrap(), 0);
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // 之前的调用已经结束，会重新执行
+        let v = group.work("k1", || async { 42 }).await;
+        assert_eq!(v, 42);
+    }
+
+    #[tokio::test]
+    async fn different_keys_should_run_in_parallel() {
+        let group = Arc::new(Group::new());
+        let start = tokio::time::Instant::now();
+        let tasks: Vec<_> = (0..4)
+            .map(|i| {
+                let group = group.clone();
+                tokio::spawn(async move {
+                    group
+                        .work(&format!("k{}", i), || async move {
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            i
+                        })
+                        .await
+                })
+            })
+            .collect();
+        for (i, task) in tasks.into_iter().enumerate() {
+            assert_eq!(task.await.unwrap(), i);
+        }
+        assert!(start.elapsed() < Duration::from_millis(300));
+    }
+}
@@ -0,0 +1,83 @@
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use std::{collections::HashMap, env, fs, sync::Arc};
+
+/// 注册的水印图片
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Named {
+    /// 和原图一样通过缓存抓取
+    Url(String),
+    /// 启动时从本地文件读取
+    Data(Bytes),
+}
+
+/// 服务器端注册的水印，spec 中通过 name 引用
+/// 通过 THUMBOR_WATERMARKS 配置，格式为 <name>=<source>，多个用逗号分隔
+/// source 是 http(s) 的 url 时通过缓存抓取，否则是本地文件的路径
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    named: Arc<HashMap<String, Named>>,
+}
+
+impl Registry {
+    pub fn from_env() -> Result<Self> {
+        match env::var("THUMBOR_WATERMARKS") {
+            Ok(v) => Self::parse(&v),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self> {
+        let mut named = HashMap::new();
+        for item in s.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (name, source) = item
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid watermark {:?}, expected <name>=<source>", item))?;
+            let watermark = if source.starts_with("http://") || source.starts_with("https://") {
+                Named::Url(source.to_owned())
+            } else {
+                let data = fs::read(source)
+                    .with_context(|| format!("cannot read watermark {} from {:?}", name, source))?;
+                Named::Data(data.into())
+            };
+            named.insert(name.to_owned(), watermark);
+        }
+        Ok(Self {
+            named: Arc::new(named),
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Named> {
+        self.named.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry_should_be_parsed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("brand.png");
+        fs::write(&path, b"png").unwrap();
+
+        let registry = Registry::parse(&format!(
+            "logo=https://example.com/logo.png, brand={}",
+            path.display()
+        ))
+        .unwrap();
+        assert_eq!(
+            registry.get("logo"),
+            Some(&Named::Url("https://example.com/logo.png".into()))
+        );
+        assert_eq!(
+            registry.get("brand"),
+            Some(&Named::Data(Bytes::from("png")))
+        );
+        assert!(registry.get("missing").is_none());
+
+        assert!(Registry::parse("logo").is_err());
+        assert!(Registry::parse("logo=/no/such/file.png").is_err());
+    }
+}